@@ -0,0 +1,87 @@
+//! Procedural macros for `ddn-tracing`.
+//!
+//! These are re-exported by `ddn-tracing`, and should be used from there.
+
+mod traceable_error;
+mod traced;
+
+use proc_macro::TokenStream;
+
+/// Runs a function in a new span, and records its result on the span.
+///
+/// This works for both sync and async functions, and is equivalent to
+/// wrapping the body in `ddn_tracing::old::Tracer::in_span`, or
+/// `in_span_future` for async functions. The return type must implement
+/// `ddn_tracing::old::Traceable`, and so must be a concrete type rather than
+/// `impl Trait`.
+///
+/// The following properties are supported, and are all optional:
+///
+///   * `name = "..."`: the span name, which defaults to the function name
+///   * `display_name = "..."`: the display name, which defaults to the span
+///     name
+///   * `visibility = "user"` or `visibility = "internal"`: the span
+///     visibility, which defaults to `"internal"`
+///   * `attributes(arg, ...)`: arguments to record as span attributes, using
+///     their `Display` implementation
+///
+/// # Example:
+/// ```ignore
+/// #[ddn_tracing::traced(name = "fetch", visibility = "user", attributes(model))]
+/// async fn fetch(model: &str, limit: usize) -> Result<Rows, FetchError> {
+///     ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn traced(attr: TokenStream, item: TokenStream) -> TokenStream {
+    traced::expand(attr, item)
+}
+
+/// Implements `ddn_tracing::old::TraceableError` for a struct or enum.
+///
+/// The visibility is set with `#[traceable(visibility = "user")]` or
+/// `#[traceable(visibility = "internal")]`, either on the type or on each
+/// variant, and defaults to `"internal"`.
+///
+/// A field marked with `#[traceable(delegate)]` is itself a
+/// `TraceableError`, and the visibility, description and details are taken
+/// from it unless they are set explicitly. An explicit visibility always takes
+/// precedence over the delegate's, whether it is set on the variant or the
+/// type, and one on a variant takes precedence over one on its enum. Its type
+/// name and backtrace are always taken from it, as is its chain of sources
+/// unless the type is marked as an error.
+///
+/// A type which implements `std::error::Error` can be marked with
+/// `#[traceable(error)]`, so that its own chain of sources is recorded on the
+/// span's exception event, such as a `#[source]` field with `thiserror`.
+///
+/// The description and details default to the `Display` and `Debug`
+/// representations. They can be set with `#[traceable(description = "...")]`
+/// and `#[traceable(details = "...")]`, which are format strings that can
+/// refer to fields by name, or by position for tuple variants.
+///
+/// # Example:
+/// ```
+/// #[derive(Debug, derive_more::Display, ddn_tracing::old::TraceableError)]
+/// #[display(fmt = "connection refused by {host}")]
+/// struct ConnectorError {
+///     host: String,
+/// }
+///
+/// #[derive(Debug, derive_more::Display, ddn_tracing::old::TraceableError)]
+/// #[traceable(visibility = "internal")]
+/// enum FetchError {
+///     #[display(fmt = "model {_0} not found")]
+///     #[traceable(visibility = "user")]
+///     ModelNotFound(String),
+///     #[display(fmt = "{_0}")]
+///     Connector(#[traceable(delegate)] ConnectorError),
+///     #[display(fmt = "timed out")]
+///     #[traceable(details = "timed out after {0:?}")]
+///     Timeout(std::time::Duration),
+/// }
+/// ```
+#[proc_macro_derive(TraceableError, attributes(traceable))]
+pub fn derive_traceable_error(input: TokenStream) -> TokenStream {
+    traceable_error::expand(input)
+}
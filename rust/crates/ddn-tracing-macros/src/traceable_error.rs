@@ -0,0 +1,279 @@
+//! The `TraceableError` derive macro.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, LitStr};
+
+/// The properties given to the `traceable` attribute on a type or variant.
+#[derive(Default)]
+struct Options {
+    visibility: Option<TokenStream2>,
+    description: Option<LitStr>,
+    details: Option<LitStr>,
+    /// Whether the type implements `std::error::Error`, so that its chain of
+    /// sources can be recorded.
+    error: bool,
+}
+
+impl Options {
+    /// Parses the `traceable` attributes. Descriptions and details are only
+    /// allowed on a single case, i.e. on a struct or variant, but not an
+    /// enum. Whether the type is an error is only allowed on the type, i.e.
+    /// on a struct or enum, but not a variant.
+    fn parse(attrs: &[Attribute], allow_messages: bool, allow_error: bool) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("traceable"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("visibility") {
+                    let visibility: LitStr = meta.value()?.parse()?;
+                    options.visibility = Some(match visibility.value().as_str() {
+                        "user" => quote!(::ddn_tracing::old::ErrorVisibility::User),
+                        "internal" => quote!(::ddn_tracing::old::ErrorVisibility::Internal),
+                        _ => {
+                            return Err(syn::Error::new(
+                                visibility.span(),
+                                "expected \"user\" or \"internal\"",
+                            ))
+                        }
+                    });
+                } else if allow_messages && meta.path.is_ident("description") {
+                    options.description = Some(named_positions(&meta.value()?.parse()?));
+                } else if allow_messages && meta.path.is_ident("details") {
+                    options.details = Some(named_positions(&meta.value()?.parse()?));
+                } else if allow_error && meta.path.is_ident("error") {
+                    options.error = true;
+                } else {
+                    return Err(meta.error("unsupported property"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// A struct, or one variant of an enum.
+struct Case {
+    /// Matches the case, binding each field to its name, or `_0`, `_1`, etc.
+    pattern: TokenStream2,
+    options: Options,
+    /// The binding of the field which the error delegates to, if any.
+    delegate: Option<Ident>,
+}
+
+impl Case {
+    fn new(path: TokenStream2, fields: &Fields, options: Options) -> syn::Result<Self> {
+        let bindings = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                field
+                    .ident
+                    .clone()
+                    .unwrap_or_else(|| format_ident!("_{index}"))
+            })
+            .collect::<Vec<_>>();
+
+        let mut delegate = None;
+        for (field, binding) in fields.iter().zip(&bindings) {
+            for attr in field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("traceable"))
+            {
+                attr.parse_nested_meta(|meta| {
+                    if !meta.path.is_ident("delegate") {
+                        return Err(meta.error("unsupported property"));
+                    }
+                    if delegate.is_some() {
+                        return Err(meta.error("only one field can be delegated to"));
+                    }
+                    delegate = Some(binding.clone());
+                    Ok(())
+                })?;
+            }
+        }
+
+        let pattern = match fields {
+            Fields::Named(_) => quote!(#path { #(#bindings),* }),
+            Fields::Unnamed(_) => quote!(#path ( #(#bindings),* )),
+            Fields::Unit => path,
+        };
+        Ok(Self {
+            pattern,
+            options,
+            delegate,
+        })
+    }
+}
+
+pub fn expand(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_derive(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_derive(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let (options, cases) = parse_cases(input)?;
+
+    // An explicit visibility, on the case or else on the enum, takes precedence
+    // over a delegate's.
+    let visibility = arms(&cases, |case| {
+        match (
+            case.options
+                .visibility
+                .as_ref()
+                .or(options.visibility.as_ref()),
+            &case.delegate,
+        ) {
+            (Some(visibility), _) => visibility.clone(),
+            (None, Some(delegate)) => {
+                quote!(::ddn_tracing::old::TraceableError::visibility(#delegate))
+            }
+            (None, None) => quote!(::ddn_tracing::old::ErrorVisibility::Internal),
+        }
+    });
+    let description = arms(&cases, |case| {
+        match (&case.options.description, &case.delegate) {
+            (Some(description), _) => quote!(::std::format!(#description)),
+            (None, Some(delegate)) => {
+                quote!(::ddn_tracing::old::TraceableError::description(#delegate))
+            }
+            (None, None) => quote!(::std::string::ToString::to_string(self)),
+        }
+    });
+    let details = arms(&cases, |case| {
+        match (&case.options.details, &case.delegate) {
+            (Some(details), _) => quote!(::std::format!(#details)),
+            (None, Some(delegate)) => {
+                quote!(::ddn_tracing::old::TraceableError::details(#delegate))
+            }
+            (None, None) => quote!(::std::format!("{self:?}")),
+        }
+    });
+    // Unless the type is marked as a standard error, the chain of sources can
+    // only be found through a delegate. The type name and backtrace always are.
+    let as_error = if options.error {
+        quote!(::std::option::Option::Some(self))
+    } else {
+        arms(&cases, |case| match &case.delegate {
+            Some(delegate) => quote!(::ddn_tracing::old::TraceableError::as_error(#delegate)),
+            None => quote!(::std::option::Option::None),
+        })
+    };
+    let type_name = arms(&cases, |case| match &case.delegate {
+        Some(delegate) => quote!(::ddn_tracing::old::TraceableError::type_name(#delegate)),
+        None => quote!(::std::option::Option::Some(::std::any::type_name::<Self>())),
+    });
+    let backtrace = arms(&cases, |case| match &case.delegate {
+        Some(delegate) => quote!(::ddn_tracing::old::TraceableError::backtrace(#delegate)),
+        None => quote!(::std::option::Option::None),
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #[allow(unused_variables)]
+        impl #impl_generics ::ddn_tracing::old::TraceableError for #ident #type_generics #where_clause {
+            fn visibility(&self) -> ::ddn_tracing::old::ErrorVisibility {
+                #visibility
+            }
+
+            fn description(&self) -> ::std::string::String {
+                #description
+            }
+
+            fn details(&self) -> ::std::string::String {
+                #details
+            }
+
+            fn type_name(&self) -> ::std::option::Option<&'static str> {
+                #type_name
+            }
+
+            fn as_error(&self) -> ::std::option::Option<&(dyn ::std::error::Error + 'static)> {
+                #as_error
+            }
+
+            fn backtrace(&self) -> ::std::option::Option<&::std::backtrace::Backtrace> {
+                #backtrace
+            }
+        }
+    })
+}
+
+/// Parses the options given to the type, and each of its cases.
+fn parse_cases(input: &DeriveInput) -> syn::Result<(Options, Vec<Case>)> {
+    match &input.data {
+        Data::Struct(data) => {
+            let case = Case::new(
+                quote!(Self),
+                &data.fields,
+                Options::parse(&input.attrs, true, true)?,
+            )?;
+            let options = Options {
+                error: case.options.error,
+                ..Options::default()
+            };
+            Ok((options, vec![case]))
+        }
+        Data::Enum(data) => {
+            let options = Options::parse(&input.attrs, false, true)?;
+            let cases = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let ident = &variant.ident;
+                    Case::new(
+                        quote!(Self::#ident),
+                        &variant.fields,
+                        Options::parse(&variant.attrs, true, false)?,
+                    )
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            Ok((options, cases))
+        }
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            input,
+            "TraceableError cannot be derived for unions",
+        )),
+    }
+}
+
+/// Matches on `self`, producing the expression given by `f` for each case.
+fn arms(cases: &[Case], f: impl Fn(&Case) -> TokenStream2) -> TokenStream2 {
+    if cases.is_empty() {
+        return quote!(match *self {});
+    }
+    let arms = cases.iter().map(|case| {
+        let pattern = &case.pattern;
+        let expression = f(case);
+        quote!(#pattern => #expression,)
+    });
+    quote!(match self { #(#arms)* })
+}
+
+/// Rewrites positional arguments in a format string, such as `{0}`, to refer
+/// to the bindings of unnamed fields, such as `{_0}`.
+fn named_positions(format: &LitStr) -> LitStr {
+    let value = format.value();
+    let mut rewritten = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        rewritten.push(c);
+        if c == '{' {
+            if chars.peek() == Some(&'{') {
+                rewritten.extend(chars.next());
+            } else if chars.peek().is_some_and(char::is_ascii_digit) {
+                rewritten.push('_');
+            }
+        }
+    }
+    LitStr::new(&rewritten, format.span())
+}
@@ -0,0 +1,152 @@
+//! The `#[traced]` attribute macro.
+
+use proc_macro::TokenStream;
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::{parse_macro_input, FnArg, Ident, ItemFn, LitStr, Pat, ReturnType};
+
+/// The properties given to the attribute.
+#[derive(Default)]
+struct TracedArgs {
+    name: Option<LitStr>,
+    display_name: Option<LitStr>,
+    visibility: Option<TokenStream2>,
+    attributes: Vec<Ident>,
+}
+
+impl TracedArgs {
+    fn parse(&mut self, meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("display_name") {
+            self.display_name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("visibility") {
+            let visibility: LitStr = meta.value()?.parse()?;
+            self.visibility = Some(match visibility.value().as_str() {
+                "user" => quote!(::ddn_tracing::old::SpanVisibility::User),
+                "internal" => quote!(::ddn_tracing::old::SpanVisibility::Internal),
+                _ => {
+                    return Err(syn::Error::new(
+                        visibility.span(),
+                        "expected \"user\" or \"internal\"",
+                    ))
+                }
+            });
+        } else if meta.path.is_ident("attributes") {
+            meta.parse_nested_meta(|meta| {
+                self.attributes.push(meta.path.require_ident()?.clone());
+                Ok(())
+            })?;
+        } else {
+            return Err(meta.error("unsupported property"));
+        }
+        Ok(())
+    }
+}
+
+pub fn expand(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = TracedArgs::default();
+    let parser = syn::meta::parser(|meta| args.parse(&meta));
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    match expand_function(args, function) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_function(args: TracedArgs, function: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+
+    let arguments = sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(typed) => match &*typed.pat {
+                Pat::Ident(pat) => Some(&pat.ident),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .collect::<Vec<_>>();
+    for attribute in &args.attributes {
+        if !arguments.contains(&attribute) {
+            return Err(syn::Error::new(
+                attribute.span(),
+                format!("`{attribute}` is not an argument of this function"),
+            ));
+        }
+    }
+
+    let name = args
+        .name
+        .unwrap_or_else(|| LitStr::new(&sig.ident.to_string(), sig.ident.span()));
+    let display_name = args.display_name.unwrap_or_else(|| name.clone());
+    let visibility = args
+        .visibility
+        .unwrap_or_else(|| quote!(::ddn_tracing::old::SpanVisibility::Internal));
+    let record_attributes = args.attributes.iter().map(|attribute| {
+        let key = attribute.to_string();
+        quote! {
+            ::ddn_tracing::old::set_attribute_on_active_span(
+                ::ddn_tracing::old::AttributeVisibility::Default,
+                #key,
+                ::std::string::ToString::to_string(&#attribute),
+            );
+        }
+    });
+    let output = match &sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, output) => quote!(#output),
+    };
+    // The result is named in the expansion, which is not possible for opaque
+    // types.
+    if contains_impl_trait(output.clone()) {
+        return Err(syn::Error::new_spanned(
+            &sig.output,
+            "`#[traced]` does not support `impl Trait` return types, as the result must have a \
+             concrete `Traceable` type",
+        ));
+    }
+
+    let body = if sig.asyncness.is_some() {
+        quote! {
+            ::ddn_tracing::old::global_tracer()
+                .in_span_future(#name, #display_name, #visibility, async move {
+                    #(#record_attributes)*
+                    let result: #output = #block;
+                    result
+                })
+                .await
+        }
+    } else {
+        quote! {
+            ::ddn_tracing::old::global_tracer()
+                .in_span(#name, #display_name, #visibility, move || -> #output {
+                    #(#record_attributes)*
+                    #block
+                })
+        }
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #body
+        }
+    })
+}
+
+/// Whether the tokens contain an `impl Trait` type, at any depth.
+fn contains_impl_trait(tokens: TokenStream2) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => ident == "impl",
+        TokenTree::Group(group) => contains_impl_trait(group.stream()),
+        TokenTree::Punct(_) | TokenTree::Literal(_) => false,
+    })
+}
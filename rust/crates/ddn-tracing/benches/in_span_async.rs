@@ -0,0 +1,73 @@
+//! Compares the runtime cost of `Tracer::in_span_async`, which boxes the
+//! future, with `Tracer::in_span_future`, which is generic over it.
+//!
+//! Spans go through a full tracing setup, so this includes recording them in
+//! the `tracing` subscriber and the OpenTelemetry layer, and queueing them
+//! for an exporter which discards them.
+//!
+//! Run with `cargo bench -p ddn-tracing`.
+//!
+//! The boxed version exists to keep compile times down in large callers, as
+//! each generic call is monomorphized separately. To compare compile times,
+//! run `rust/testing/span-compile-times/compare.sh`, which builds a fixture
+//! with many call sites in each style and prints how long each took.
+
+use std::convert::Infallible;
+use std::hint::black_box;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ddn_tracing::export::{ExportResult, SpanData, SpanExporter};
+use ddn_tracing::old::{global_tracer, SpanVisibility};
+use ddn_tracing::setup::{Builder, Exporter};
+use futures_util::future::BoxFuture;
+
+fn in_span_async(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the runtime");
+    // Record spans for real, without exporting them anywhere.
+    let scoped = {
+        let _runtime = runtime.enter();
+        Builder::new("bench", "1.0.0")
+            .with_exporter(Exporter::custom(DiscardingExporter))
+            .with_log_writer(std::io::sink)
+            .build()
+            .expect("failed to set up tracing")
+    };
+    let subscriber = ddn_tracing::tracing::subscriber::set_default(scoped.subscriber());
+    let tracer = global_tracer();
+
+    let mut group = c.benchmark_group("in_span_async");
+    group.bench_function("boxed", |b| {
+        b.to_async(&runtime).iter(|| {
+            tracer.in_span_async("span", "span", SpanVisibility::User, || {
+                Box::pin(async { Ok::<_, Infallible>(black_box(42)) })
+            })
+        });
+    });
+    group.bench_function("generic", |b| {
+        b.to_async(&runtime).iter(|| {
+            tracer.in_span_future("span", "span", SpanVisibility::User, async {
+                Ok::<_, Infallible>(black_box(42))
+            })
+        });
+    });
+    group.finish();
+
+    drop(subscriber);
+    let _ = runtime.block_on(scoped.shutdown(Duration::from_secs(5)));
+}
+
+#[derive(Debug)]
+struct DiscardingExporter;
+
+impl SpanExporter for DiscardingExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+criterion_group!(benches, in_span_async);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+//! Records the compiler version, so it can be reported as a resource attribute.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!(
+        "cargo:rustc-env=DDN_TRACING_RUSTC_VERSION={}",
+        version.trim()
+    );
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
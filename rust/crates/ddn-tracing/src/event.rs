@@ -0,0 +1,94 @@
+//! Structured events, recorded on `tracing` spans.
+//!
+//! `tracing::info!` and friends already produce span events, but they are
+//! always timestamped "now", and their fields cannot be marked as internal.
+//! [`Event`] allows both.
+
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+use opentelemetry::{Key, KeyValue, Value};
+
+use crate::export::INTERNAL_ATTRIBUTE_PREFIX;
+use crate::otel_data::with_otel_data;
+
+/// An event to be recorded on a span.
+///
+/// Events recorded this way are exported with the span, but are not written
+/// to the log output.
+///
+/// # Example:
+/// ```
+/// ddn_tracing::event::Event::new("query planned")
+///     .with_attribute("cache.key", "abc123")
+///     .with_attribute("rows", 42)
+///     .with_internal_attribute("plan", "HashJoin(...)")
+///     .record();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Event {
+    name: Cow<'static, str>,
+    timestamp: Option<SystemTime>,
+    attributes: Vec<KeyValue>,
+}
+
+impl Event {
+    /// Creates an event with no attributes.
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            timestamp: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute.
+    #[must_use]
+    pub fn with_attribute(mut self, key: impl Into<Key>, value: impl Into<Value>) -> Self {
+        self.attributes.push(KeyValue::new(key, value));
+        self
+    }
+
+    /// Adds an attribute which is only exported to internal destinations, by
+    /// prefixing the key with `internal.`.
+    #[must_use]
+    pub fn with_internal_attribute(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.attributes.push(KeyValue::new(
+            format!("{INTERNAL_ATTRIBUTE_PREFIX}{key}"),
+            value,
+        ));
+        self
+    }
+
+    /// Sets the time at which the event happened. This defaults to the time
+    /// at which it is recorded.
+    #[must_use]
+    pub fn with_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Records the event on the current span.
+    pub fn record(self) {
+        self.record_on(&tracing::Span::current());
+    }
+
+    /// Records the event on the given span.
+    ///
+    /// This does nothing if the span is disabled, or is not being exported.
+    pub fn record_on(self, span: &tracing::Span) {
+        let event = opentelemetry::trace::Event::new(
+            self.name,
+            self.timestamp.unwrap_or_else(SystemTime::now),
+            self.attributes,
+            0,
+        );
+        with_otel_data(span, |otel_data| {
+            otel_data
+                .builder
+                .events
+                .get_or_insert_with(Vec::new)
+                .push(event);
+        });
+    }
+}
@@ -0,0 +1,169 @@
+//! Prints spans to standard error, for local development.
+//!
+//! This is useful for seeing spans without running a collector.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use opentelemetry::trace::{SpanId, Status, TraceError, TraceId};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+
+use super::otlp_json;
+
+/// How to print spans.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConsoleFormat {
+    /// An indented tree of spans for each trace, with durations, attributes,
+    /// and events.
+    #[default]
+    Tree,
+    /// One line of OTLP JSON for each batch of spans.
+    OtlpJson,
+}
+
+/// Prints finished spans to standard error, or another writer.
+///
+/// Spans are printed in batches. In [`ConsoleFormat::Tree`] format, spans are
+/// grouped by trace within each batch; a span whose parent is not in the same
+/// batch is printed at the top level.
+pub struct ConsoleExporter {
+    format: ConsoleFormat,
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl ConsoleExporter {
+    /// Creates a new exporter which prints to standard error.
+    pub fn new(format: ConsoleFormat) -> Self {
+        Self::with_writer(format, io::stderr())
+    }
+
+    /// Creates a new exporter which prints to the given writer.
+    pub fn with_writer(format: ConsoleFormat, writer: impl Write + Send + Sync + 'static) -> Self {
+        Self {
+            format,
+            writer: Box::new(writer),
+        }
+    }
+
+    fn write(&mut self, batch: Vec<SpanData>) -> io::Result<()> {
+        let output = match self.format {
+            ConsoleFormat::Tree => render_tree(&batch),
+            ConsoleFormat::OtlpJson => otlp_json::encode(batch)? + "\n",
+        };
+        self.writer.write_all(output.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl std::fmt::Debug for ConsoleExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConsoleExporter")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpanExporter for ConsoleExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = self
+            .write(batch)
+            .map_err(|error| TraceError::Other(Box::new(error)));
+        Box::pin(async move { result })
+    }
+}
+
+/// Renders a batch of spans as one tree per trace.
+fn render_tree(batch: &[SpanData]) -> String {
+    // Traces are printed in the order they first appear in the batch.
+    let mut traces: Vec<(TraceId, Vec<&SpanData>)> = Vec::new();
+    for span in batch {
+        let trace_id = span.span_context.trace_id();
+        match traces.iter_mut().find(|(id, _)| *id == trace_id) {
+            Some((_, spans)) => spans.push(span),
+            None => traces.push((trace_id, vec![span])),
+        }
+    }
+
+    let mut output = String::new();
+    for (trace_id, spans) in traces {
+        let span_ids = spans
+            .iter()
+            .map(|span| span.span_context.span_id())
+            .collect::<Vec<_>>();
+        let mut children: HashMap<SpanId, Vec<&SpanData>> = HashMap::new();
+        let mut roots = Vec::new();
+        for span in spans {
+            if span_ids.contains(&span.parent_span_id) {
+                children.entry(span.parent_span_id).or_default().push(span);
+            } else {
+                roots.push(span);
+            }
+        }
+
+        let _ = writeln!(output, "trace {trace_id}");
+        roots.sort_by_key(|span| span.start_time);
+        for root in roots {
+            render_span(&mut output, root, &mut children, 1);
+        }
+    }
+    output
+}
+
+/// Renders a span and its children, indented according to their depth.
+fn render_span(
+    output: &mut String,
+    span: &SpanData,
+    children: &mut HashMap<SpanId, Vec<&SpanData>>,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    let duration = span
+        .end_time
+        .duration_since(span.start_time)
+        .unwrap_or_default();
+    let _ = write!(
+        output,
+        "{indent}{} [{}]",
+        span.name,
+        format_duration(duration)
+    );
+    for attribute in &span.attributes {
+        let _ = write!(output, " {}={}", attribute.key, attribute.value);
+    }
+    if let Status::Error { description } = &span.status {
+        let _ = write!(output, " error={description:?}");
+    }
+    output.push('\n');
+
+    for event in span.events.iter() {
+        let offset = event
+            .timestamp
+            .duration_since(span.start_time)
+            .unwrap_or_default();
+        let _ = write!(
+            output,
+            "{indent}  - {} [+{}]",
+            event.name,
+            format_duration(offset)
+        );
+        for attribute in &event.attributes {
+            let _ = write!(output, " {}={}", attribute.key, attribute.value);
+        }
+        output.push('\n');
+    }
+
+    let mut span_children = children
+        .remove(&span.span_context.span_id())
+        .unwrap_or_default();
+    span_children.sort_by_key(|child| child.start_time);
+    for child in span_children {
+        render_span(output, child, children, depth + 1);
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,221 @@
+//! Appends spans to local files, for installations without a collector.
+//!
+//! Each batch of spans is written as one line of OTLP JSON, so the files can
+//! be read by anything which understands the OTLP file format:
+//!
+//!   * https://opentelemetry.io/docs/specs/otel/protocol/file-exporter/
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::BoxFuture;
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+
+use super::otlp_json;
+
+const DEFAULT_FILE_PREFIX: &str = "traces";
+const FILE_EXTENSION: &str = "jsonl";
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_MAX_FILES: usize = 10;
+
+/// Appends spans to files in a directory, in the OTLP JSON encoding.
+///
+/// Files are named `<prefix>.<sequence number>.jsonl`. A new file is started
+/// when the current one grows beyond the maximum size or age, and the oldest
+/// files are deleted so that at most the maximum number of files are kept.
+/// Rotation is checked whenever spans are written, so an idle file may be
+/// older than the maximum age.
+///
+/// Files are written on Tokio's blocking thread pool, so that slow disks do
+/// not hold up other tasks.
+#[derive(Debug)]
+pub struct FileExporter {
+    settings: Settings,
+    current: Arc<Mutex<Option<CurrentFile>>>,
+}
+
+/// Where files are written, and when they are rotated.
+#[derive(Clone, Debug)]
+struct Settings {
+    directory: PathBuf,
+    file_prefix: String,
+    max_file_size: u64,
+    max_file_age: Duration,
+    max_files: usize,
+}
+
+/// The file currently being appended to.
+#[derive(Debug)]
+struct CurrentFile {
+    file: fs::File,
+    sequence_number: u64,
+    size: u64,
+    opened_at: Instant,
+}
+
+impl FileExporter {
+    /// Creates an exporter which writes to the given directory, creating it
+    /// if necessary.
+    ///
+    /// By default, files are rotated when they reach 10 MiB or are an hour
+    /// old, and 10 files are kept.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            settings: Settings {
+                directory: directory.into(),
+                file_prefix: DEFAULT_FILE_PREFIX.to_owned(),
+                max_file_size: DEFAULT_MAX_FILE_SIZE,
+                max_file_age: DEFAULT_MAX_FILE_AGE,
+                max_files: DEFAULT_MAX_FILES,
+            },
+            current: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sets the prefix of file names. This defaults to `traces`.
+    #[must_use]
+    pub fn with_file_prefix(mut self, file_prefix: impl Into<String>) -> Self {
+        self.settings.file_prefix = file_prefix.into();
+        self
+    }
+
+    /// Sets the size, in bytes, after which a new file is started.
+    ///
+    /// Batches are never split, so a file may grow beyond this by up to one
+    /// batch.
+    #[must_use]
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.settings.max_file_size = max_file_size;
+        self
+    }
+
+    /// Sets the age after which a new file is started.
+    #[must_use]
+    pub fn with_max_file_age(mut self, max_file_age: Duration) -> Self {
+        self.settings.max_file_age = max_file_age;
+        self
+    }
+
+    /// Sets the number of files to keep, including the current one. This is
+    /// at least 1.
+    #[must_use]
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.settings.max_files = max_files.max(1);
+        self
+    }
+}
+
+impl Settings {
+    fn write(&self, current: &mut Option<CurrentFile>, batch: Vec<SpanData>) -> io::Result<()> {
+        let line = otlp_json::encode(batch)? + "\n";
+        let current = self.current_file(current)?;
+        current.file.write_all(line.as_bytes())?;
+        current.file.flush()?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Gets the file to write to, starting a new one if necessary.
+    fn current_file<'a>(
+        &self,
+        current: &'a mut Option<CurrentFile>,
+    ) -> io::Result<&'a mut CurrentFile> {
+        let next_sequence_number = match current {
+            None => None,
+            Some(current)
+                if current.size >= self.max_file_size
+                    || current.opened_at.elapsed() >= self.max_file_age =>
+            {
+                Some(current.sequence_number + 1)
+            }
+            Some(current) => return Ok(current),
+        };
+
+        fs::create_dir_all(&self.directory)?;
+        let sequence_number = match next_sequence_number {
+            Some(sequence_number) => sequence_number,
+            // Carry on after any files left by a previous process.
+            None => self
+                .existing_files()?
+                .last()
+                .map_or(0, |(sequence_number, _)| sequence_number + 1),
+        };
+        let file = fs::OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(self.path(sequence_number))?;
+        let current = current.insert(CurrentFile {
+            file,
+            sequence_number,
+            size: 0,
+            opened_at: Instant::now(),
+        });
+        self.delete_old_files()?;
+        Ok(current)
+    }
+
+    /// Deletes the oldest files, keeping at most the maximum number.
+    fn delete_old_files(&self) -> io::Result<()> {
+        let files = self.existing_files()?;
+        let excess = files.len().saturating_sub(self.max_files);
+        for (_, path) in files.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the files written by this exporter, oldest first.
+    fn existing_files(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if let Some(sequence_number) = self.sequence_number(&path) {
+                files.push((sequence_number, path));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn path(&self, sequence_number: u64) -> PathBuf {
+        self.directory.join(format!(
+            "{}.{sequence_number}.{FILE_EXTENSION}",
+            self.file_prefix
+        ))
+    }
+
+    /// Parses the sequence number from a file name, if the file was written
+    /// by this exporter.
+    fn sequence_number(&self, path: &Path) -> Option<u64> {
+        path.file_name()?
+            .to_str()?
+            .strip_prefix(&self.file_prefix)?
+            .strip_prefix('.')?
+            .strip_suffix(FILE_EXTENSION)?
+            .strip_suffix('.')?
+            .parse()
+            .ok()
+    }
+}
+
+impl SpanExporter for FileExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let settings = self.settings.clone();
+        let current = self.current.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || settings.write(&mut current.lock().unwrap(), batch))
+                .await
+                .map_err(|error| TraceError::Other(Box::new(error)))?
+                .map_err(|error| TraceError::Other(Box::new(error)))
+        })
+    }
+
+    fn shutdown(&mut self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,20 @@
+//! Span exporters, and wrappers around them.
+
+mod console;
+mod file;
+mod otlp_json;
+mod redact;
+mod retry;
+mod stats;
+
+pub use console::{ConsoleExporter, ConsoleFormat};
+pub use file::FileExporter;
+pub use redact::{RedactingExporter, Redaction, RedactionMode};
+pub use retry::{RetryExporter, RetryQueue};
+pub use stats::ExportStats;
+
+// re-export things from OpenTelemetry so library users can write their own
+// exporters without importing their own version
+pub use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+pub(crate) use redact::INTERNAL_ATTRIBUTE_PREFIX;
+pub(crate) use stats::{ExportQueue, StatsExporter};
@@ -0,0 +1,255 @@
+//! Encodes spans in the OTLP JSON encoding, and decodes them again.
+//!
+//! See https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use opentelemetry::trace::{
+    Event, Link, SpanContext, SpanId, SpanKind, Status, TraceError, TraceFlags, TraceId, TraceState,
+};
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope};
+use opentelemetry_proto::tonic::trace::v1::{span, status, ResourceSpans, Span, TracesData};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{SpanEvents, SpanLinks};
+use opentelemetry_sdk::{InstrumentationLibrary, Resource};
+
+/// Encodes a batch of spans as a single line of JSON, representing a
+/// `TracesData` message, without a trailing newline.
+pub(crate) fn encode(batch: Vec<SpanData>) -> serde_json::Result<String> {
+    let traces_data = TracesData {
+        resource_spans: batch.into_iter().map(ResourceSpans::from).collect(),
+    };
+    serde_json::to_string(&traces_data)
+}
+
+/// Decodes a batch of spans encoded by [`encode`].
+///
+/// Attribute values which cannot be represented by OpenTelemetry, such as
+/// maps and mixed arrays, are kept as their JSON encoding.
+pub(crate) fn decode(line: &str) -> Result<Vec<SpanData>, TraceError> {
+    let traces_data: TracesData =
+        serde_json::from_str(line).map_err(|error| TraceError::Other(Box::new(error)))?;
+
+    let mut batch = Vec::new();
+    for resource_spans in traces_data.resource_spans {
+        let attributes = resource_spans
+            .resource
+            .map(|resource| decode_attributes(resource.attributes))
+            .unwrap_or_default();
+        let resource = if resource_spans.schema_url.is_empty() {
+            Resource::new(attributes)
+        } else {
+            Resource::from_schema_url(attributes, resource_spans.schema_url)
+        };
+        for scope_spans in resource_spans.scope_spans {
+            let instrumentation_lib = decode_scope(
+                scope_spans.scope.unwrap_or_default(),
+                scope_spans.schema_url,
+            );
+            for span in scope_spans.spans {
+                batch.push(decode_span(span, &resource, &instrumentation_lib)?);
+            }
+        }
+    }
+    Ok(batch)
+}
+
+fn decode_span(
+    span: Span,
+    resource: &Resource,
+    instrumentation_lib: &InstrumentationLibrary,
+) -> Result<SpanData, TraceError> {
+    let span_context = SpanContext::new(
+        decode_trace_id(&span.trace_id)?,
+        decode_span_id(&span.span_id)?,
+        decode_trace_flags(span.flags),
+        false,
+        decode_trace_state(&span.trace_state),
+    );
+    let parent_span_id = if span.parent_span_id.is_empty() {
+        SpanId::INVALID
+    } else {
+        decode_span_id(&span.parent_span_id)?
+    };
+
+    let mut events = SpanEvents::default();
+    events.dropped_count = span.dropped_events_count;
+    events.events = span
+        .events
+        .into_iter()
+        .map(|event| {
+            Event::new(
+                event.name,
+                decode_time(event.time_unix_nano),
+                decode_attributes(event.attributes),
+                event.dropped_attributes_count,
+            )
+        })
+        .collect();
+
+    let mut links = SpanLinks::default();
+    links.dropped_count = span.dropped_links_count;
+    links.links = span
+        .links
+        .into_iter()
+        .map(|link| {
+            let span_context = SpanContext::new(
+                decode_trace_id(&link.trace_id)?,
+                decode_span_id(&link.span_id)?,
+                decode_trace_flags(link.flags),
+                false,
+                decode_trace_state(&link.trace_state),
+            );
+            let mut decoded = Link::new(span_context, decode_attributes(link.attributes));
+            decoded.dropped_attributes_count = link.dropped_attributes_count;
+            Ok(decoded)
+        })
+        .collect::<Result<_, TraceError>>()?;
+
+    let status = match span.status {
+        Some(status) if status.code == status::StatusCode::Error as i32 => {
+            Status::error(status.message)
+        }
+        Some(status) if status.code == status::StatusCode::Ok as i32 => Status::Ok,
+        _ => Status::Unset,
+    };
+
+    Ok(SpanData {
+        span_context,
+        parent_span_id,
+        span_kind: decode_span_kind(span.kind),
+        name: Cow::Owned(span.name),
+        start_time: decode_time(span.start_time_unix_nano),
+        end_time: decode_time(span.end_time_unix_nano),
+        attributes: decode_attributes(span.attributes),
+        dropped_attributes_count: span.dropped_attributes_count,
+        events,
+        links,
+        status,
+        resource: Cow::Owned(resource.clone()),
+        instrumentation_lib: instrumentation_lib.clone(),
+    })
+}
+
+fn decode_scope(scope: InstrumentationScope, schema_url: String) -> InstrumentationLibrary {
+    InstrumentationLibrary::new(
+        scope.name,
+        Some(scope.version).filter(|version| !version.is_empty()),
+        Some(schema_url).filter(|schema_url| !schema_url.is_empty()),
+        Some(decode_attributes(scope.attributes)),
+    )
+}
+
+fn decode_trace_id(bytes: &[u8]) -> Result<TraceId, TraceError> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| TraceError::from(format!("invalid trace ID: {bytes:?}")))?;
+    Ok(TraceId::from_bytes(bytes))
+}
+
+fn decode_span_id(bytes: &[u8]) -> Result<SpanId, TraceError> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| TraceError::from(format!("invalid span ID: {bytes:?}")))?;
+    Ok(SpanId::from_bytes(bytes))
+}
+
+/// Only the lower 8 bits of the flags are trace flags.
+fn decode_trace_flags(flags: u32) -> TraceFlags {
+    TraceFlags::new(flags.to_le_bytes()[0])
+}
+
+/// Invalid trace state is ignored, as it would be when propagated.
+fn decode_trace_state(header: &str) -> TraceState {
+    TraceState::from_str(header).unwrap_or_default()
+}
+
+fn decode_span_kind(kind: i32) -> SpanKind {
+    match span::SpanKind::try_from(kind) {
+        Ok(span::SpanKind::Server) => SpanKind::Server,
+        Ok(span::SpanKind::Client) => SpanKind::Client,
+        Ok(span::SpanKind::Producer) => SpanKind::Producer,
+        Ok(span::SpanKind::Consumer) => SpanKind::Consumer,
+        _ => SpanKind::Internal,
+    }
+}
+
+fn decode_time(nanos: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+/// Decodes attributes, skipping any without a value.
+fn decode_attributes(
+    attributes: Vec<opentelemetry_proto::tonic::common::v1::KeyValue>,
+) -> Vec<KeyValue> {
+    attributes
+        .into_iter()
+        .filter_map(|attribute| {
+            let value = decode_value(&attribute.value?)?;
+            Some(KeyValue::new(attribute.key, value))
+        })
+        .collect()
+}
+
+fn decode_value(value: &AnyValue) -> Option<Value> {
+    match value.value.as_ref()? {
+        any_value::Value::StringValue(string) => Some(Value::String(string.clone().into())),
+        any_value::Value::BoolValue(bool) => Some(Value::Bool(*bool)),
+        any_value::Value::IntValue(int) => Some(Value::I64(*int)),
+        any_value::Value::DoubleValue(double) => Some(Value::F64(*double)),
+        any_value::Value::ArrayValue(array) => {
+            Some(decode_array(&array.values).map_or_else(|| json_value(value), Value::Array))
+        }
+        any_value::Value::KvlistValue(_) | any_value::Value::BytesValue(_) => {
+            Some(json_value(value))
+        }
+    }
+}
+
+/// Decodes an array whose elements all have the same primitive type. Empty
+/// arrays are decoded as arrays of strings.
+fn decode_array(values: &[AnyValue]) -> Option<Array> {
+    fn collect<T>(
+        values: &[AnyValue],
+        element: impl Fn(&any_value::Value) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        values
+            .iter()
+            .map(|value| value.value.as_ref().and_then(&element))
+            .collect()
+    }
+
+    let Some(first) = values.first() else {
+        return Some(Array::String(Vec::new()));
+    };
+    match first.value.as_ref()? {
+        any_value::Value::StringValue(_) => collect(values, |value| match value {
+            any_value::Value::StringValue(string) => Some(StringValue::from(string.clone())),
+            _ => None,
+        })
+        .map(Array::String),
+        any_value::Value::BoolValue(_) => collect(values, |value| match value {
+            any_value::Value::BoolValue(bool) => Some(*bool),
+            _ => None,
+        })
+        .map(Array::Bool),
+        any_value::Value::IntValue(_) => collect(values, |value| match value {
+            any_value::Value::IntValue(int) => Some(*int),
+            _ => None,
+        })
+        .map(Array::I64),
+        any_value::Value::DoubleValue(_) => collect(values, |value| match value {
+            any_value::Value::DoubleValue(double) => Some(*double),
+            _ => None,
+        })
+        .map(Array::F64),
+        _ => None,
+    }
+}
+
+fn json_value(value: &AnyValue) -> Value {
+    Value::String(serde_json::to_string(value).unwrap_or_default().into())
+}
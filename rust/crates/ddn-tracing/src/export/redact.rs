@@ -0,0 +1,168 @@
+//! Redacts span attributes before export, so that the same spans can be sent
+//! both to internal backends and to customer-facing ones.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use futures_util::future::BoxFuture;
+use opentelemetry::{Key, KeyValue, Value};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use sha2::{Digest, Sha256};
+
+/// The prefix of attributes which are only meant for internal consumers,
+/// e.g. those set with [`crate::old::AttributeVisibility::Internal`].
+pub(crate) const INTERNAL_ATTRIBUTE_PREFIX: &str = "internal.";
+
+/// How redacted attributes are treated.
+#[derive(Clone, Default)]
+pub enum RedactionMode {
+    /// Removes the attributes.
+    #[default]
+    Strip,
+    /// Replaces each value with `sha256:<hex digest>` of the salt followed by
+    /// the value, so equal values can still be correlated.
+    ///
+    /// Use a secret salt for values which could be guessed, such as email
+    /// addresses.
+    Hash { salt: Vec<u8> },
+}
+
+/// The salt may be secret, so we only show whether it is set.
+impl std::fmt::Debug for RedactionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strip => f.write_str("Strip"),
+            Self::Hash { salt } => f
+                .debug_struct("Hash")
+                .field("salt", &!salt.is_empty())
+                .finish(),
+        }
+    }
+}
+
+/// Chooses which attributes to redact, on spans, their events and their
+/// links.
+///
+/// By default, attributes prefixed with `internal.` are stripped.
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    mode: RedactionMode,
+    redact_internal: bool,
+    sensitive_keys: HashSet<Key>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            mode: RedactionMode::default(),
+            redact_internal: true,
+            sensitive_keys: HashSet::new(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Creates a redaction which strips internal attributes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how redacted attributes are treated.
+    #[must_use]
+    pub fn with_mode(mut self, mode: RedactionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets whether attributes prefixed with `internal.` are redacted. This
+    /// defaults to `true`.
+    #[must_use]
+    pub fn with_redact_internal(mut self, redact_internal: bool) -> Self {
+        self.redact_internal = redact_internal;
+        self
+    }
+
+    /// Also redacts attributes with exactly this key.
+    #[must_use]
+    pub fn with_sensitive_key(mut self, key: impl Into<Key>) -> Self {
+        self.sensitive_keys.insert(key.into());
+        self
+    }
+
+    fn is_redacted(&self, key: &Key) -> bool {
+        (self.redact_internal && key.as_str().starts_with(INTERNAL_ATTRIBUTE_PREFIX))
+            || self.sensitive_keys.contains(key)
+    }
+
+    fn redact(&self, attributes: &mut Vec<KeyValue>) {
+        match &self.mode {
+            RedactionMode::Strip => {
+                attributes.retain(|attribute| !self.is_redacted(&attribute.key));
+            }
+            RedactionMode::Hash { salt } => {
+                for attribute in attributes {
+                    if self.is_redacted(&attribute.key) {
+                        attribute.value = hash(salt, &attribute.value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn redact_span(&self, span: &mut SpanData) {
+        self.redact(&mut span.attributes);
+        for event in &mut span.events.events {
+            self.redact(&mut event.attributes);
+        }
+        for link in &mut span.links.links {
+            self.redact(&mut link.attributes);
+        }
+    }
+}
+
+fn hash(salt: &[u8], value: &Value) -> Value {
+    let digest = Sha256::new()
+        .chain_update(salt)
+        .chain_update(value.as_str().as_bytes())
+        .finalize();
+    let mut hashed = String::from("sha256:");
+    for byte in digest {
+        let _ = write!(hashed, "{byte:02x}");
+    }
+    hashed.into()
+}
+
+/// Wraps an exporter, redacting attributes before they are exported.
+#[derive(Debug)]
+pub struct RedactingExporter {
+    inner: Box<dyn SpanExporter>,
+    redaction: Redaction,
+}
+
+impl RedactingExporter {
+    /// Wraps the given exporter.
+    pub fn new(inner: impl SpanExporter + 'static, redaction: Redaction) -> Self {
+        Self::from_boxed(Box::new(inner), redaction)
+    }
+
+    pub(crate) fn from_boxed(inner: Box<dyn SpanExporter>, redaction: Redaction) -> Self {
+        Self { inner, redaction }
+    }
+}
+
+impl SpanExporter for RedactingExporter {
+    fn export(&mut self, mut batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        for span in &mut batch {
+            self.redaction.redact_span(span);
+        }
+        self.inner.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.inner.force_flush()
+    }
+}
@@ -0,0 +1,461 @@
+//! Retries spans which could not be exported, spilling them to disk while
+//! the endpoint is unavailable.
+//!
+//! Each batch which cannot be sent is written to its own file, in the OTLP
+//! JSON encoding, and deleted once it has been sent. Files left behind by a
+//! previous process are sent too.
+//!
+//! This includes batches which the batch span processor gives up on because
+//! they took too long to export.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use tokio::task::JoinHandle;
+
+use super::{otlp_json, ExportStats};
+
+const FILE_PREFIX: &str = "batch";
+const FILE_EXTENSION: &str = "json";
+const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Configures where and for how long spans are kept while they cannot be
+/// exported. See [`RetryExporter`].
+#[derive(Clone, Debug)]
+pub struct RetryQueue {
+    directory: PathBuf,
+    max_size: u64,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryQueue {
+    /// Creates a queue which spills to the given directory, creating it if
+    /// necessary. The directory should not be shared with anything else.
+    ///
+    /// By default, up to 64 MiB of spans are kept, and retries back off from
+    /// 1 second to 1 minute.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Sets the total size, in bytes, of the spilled batches. Once this is
+    /// reached, further batches fail to export until some have been sent.
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets how long to wait before the first retry. This doubles after each
+    /// failed retry, up to the maximum backoff.
+    #[must_use]
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Sets the longest time to wait between retries.
+    #[must_use]
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+}
+
+/// Wraps an exporter, spilling batches which it could not send to a bounded
+/// queue on disk, and retrying them with exponential backoff.
+///
+/// Batches which fail, or are abandoned by the batch span processor because
+/// they take too long, are spilled. Spilled spans are counted separately in
+/// [`ExportStats`], and only counted as exported once they have been sent.
+/// Once anything has been spilled, new batches are spilled too, behind it,
+/// until the queue has been emptied, so that an unavailable endpoint is not
+/// tried for every batch.
+///
+/// Batches are retried until they are sent, so this should only wrap
+/// exporters which fail because the endpoint is unavailable, rather than
+/// because of the spans themselves.
+///
+/// Spilled batches are read and written on Tokio's blocking thread pool, so
+/// that slow disks do not hold up other tasks.
+#[derive(Debug)]
+pub struct RetryExporter {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    inner: Mutex<Box<dyn SpanExporter>>,
+    settings: RetryQueue,
+    spilled: Mutex<Spilled>,
+    stats: ExportStats,
+}
+
+/// The batches on disk, oldest first.
+#[derive(Debug, Default)]
+struct Spilled {
+    /// Whether files left by a previous process have been found yet.
+    loaded: bool,
+    files: VecDeque<SpilledFile>,
+    size: u64,
+    next_sequence_number: u64,
+    /// The task sending spilled batches, while there are any.
+    retrying: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct SpilledFile {
+    path: PathBuf,
+    size: u64,
+}
+
+impl RetryExporter {
+    /// Wraps the given exporter, counting the spans it exports and spills in
+    /// the given stats.
+    pub fn new(inner: impl SpanExporter + 'static, queue: RetryQueue, stats: ExportStats) -> Self {
+        Self::from_boxed(Box::new(inner), queue, stats)
+    }
+
+    pub(crate) fn from_boxed(
+        inner: Box<dyn SpanExporter>,
+        queue: RetryQueue,
+        stats: ExportStats,
+    ) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                inner: Mutex::new(inner),
+                settings: queue,
+                spilled: Mutex::new(Spilled::default()),
+                stats,
+            }),
+        }
+    }
+}
+
+impl SpanExporter for RetryExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        // This is created outside the future, so that the batch is spilled
+        // even if the future is dropped before it is finished.
+        let mut pending = PendingBatch {
+            shared: self.shared.clone(),
+            state: Pending::Unsent(batch),
+        };
+        Box::pin(async move {
+            let shared = pending.shared.clone();
+            match shared.is_empty().await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let batch = pending.take().expect("the batch has not been sent");
+                    return shared.spill_in_background(batch).await;
+                }
+                Err(error) => {
+                    pending.finish(false);
+                    return Err(error);
+                }
+            }
+            let export = shared.inner.lock().unwrap().export(pending.hand_over());
+            if export.await.is_ok() {
+                pending.finish(true);
+                Ok(())
+            } else {
+                let batch = pending.take().expect("the batch has not been spilled");
+                shared.spill_in_background(batch).await
+            }
+        })
+    }
+
+    fn shutdown(&mut self) {
+        // Anything still spilled is sent by the next process.
+        if let Some(retrying) = self.shared.spilled.lock().unwrap().retrying.take() {
+            retrying.abort();
+        }
+        self.shared.inner.lock().unwrap().shutdown();
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.shared.inner.lock().unwrap().force_flush()
+    }
+}
+
+/// A batch which is being exported, which is spilled if this is dropped
+/// before the export has finished, e.g. because it timed out.
+struct PendingBatch {
+    shared: Arc<Shared>,
+    state: Pending,
+}
+
+enum Pending {
+    /// The batch has not been handed to the exporter yet.
+    Unsent(Vec<SpanData>),
+    /// The batch has been handed to the exporter. Exporters do not give it
+    /// back if they fail or time out, so this is a copy to spill, which is
+    /// dropped as soon as the export succeeds.
+    Sending(Vec<SpanData>),
+    /// The batch has been exported, spilled, or counted as failed.
+    Done,
+}
+
+impl PendingBatch {
+    /// Takes the batch to be exported, keeping a copy to spill.
+    fn hand_over(&mut self) -> Vec<SpanData> {
+        let Pending::Unsent(batch) = std::mem::replace(&mut self.state, Pending::Done) else {
+            panic!("the batch has already been handed over");
+        };
+        self.state = Pending::Sending(batch.clone());
+        batch
+    }
+
+    /// Takes the batch to spill it, if there is anything to spill.
+    fn take(&mut self) -> Option<Vec<SpanData>> {
+        match std::mem::replace(&mut self.state, Pending::Done) {
+            Pending::Unsent(batch) | Pending::Sending(batch) => Some(batch),
+            Pending::Done => None,
+        }
+    }
+
+    /// Counts the batch as exported or failed.
+    fn finish(&mut self, exported: bool) {
+        if let Some(batch) = self.take() {
+            self.shared.stats.record(batch.len() as u64, exported);
+        }
+    }
+}
+
+impl Drop for PendingBatch {
+    fn drop(&mut self) {
+        let Some(batch) = self.take() else {
+            return;
+        };
+        let shared = self.shared.clone();
+        let spill = move || {
+            if let Err(error) = shared.spill(batch) {
+                opentelemetry::global::handle_error(error);
+            }
+        };
+        // Outside a runtime, e.g. if the pipeline is being dropped, this
+        // blocks instead.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(spill)),
+            Err(_) => spill(),
+        }
+    }
+}
+
+impl Shared {
+    /// Checks whether anything is spilled, first finding any files left by a
+    /// previous process.
+    async fn is_empty(self: &Arc<Self>) -> Result<bool, TraceError> {
+        if !self.spilled.lock().unwrap().loaded {
+            let shared = self.clone();
+            run_blocking(move || shared.load().map_err(io_error)).await?;
+        }
+        let mut spilled = self.spilled.lock().unwrap();
+        if !spilled.files.is_empty() && spilled.retrying.is_none() {
+            spilled.retrying = Some(tokio::spawn(self.clone().retry()));
+        }
+        Ok(spilled.files.is_empty())
+    }
+
+    /// Finds any files left by a previous process, the first time this is
+    /// called. This blocks.
+    fn load(&self) -> io::Result<()> {
+        if self.spilled.lock().unwrap().loaded {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.settings.directory)?;
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.settings.directory)? {
+            let entry = entry?;
+            if let Some(sequence_number) = sequence_number(&entry.path()) {
+                files.push((sequence_number, entry.path(), entry.metadata()?.len()));
+            }
+        }
+        files.sort();
+
+        let mut spilled = self.spilled.lock().unwrap();
+        // Nothing is spilled until this has been loaded, so if it has been
+        // loaded in the meantime, we found the same files.
+        if spilled.loaded {
+            return Ok(());
+        }
+        for (sequence_number, path, size) in files {
+            spilled.next_sequence_number = sequence_number + 1;
+            spilled.size += size;
+            spilled.files.push_back(SpilledFile { path, size });
+        }
+        spilled.loaded = true;
+        Ok(())
+    }
+
+    /// Spills a batch on the blocking thread pool.
+    async fn spill_in_background(self: &Arc<Self>, batch: Vec<SpanData>) -> ExportResult {
+        let shared = self.clone();
+        run_blocking(move || shared.spill(batch)).await
+    }
+
+    /// Writes a batch to disk, to be retried later, and starts retrying if
+    /// necessary. The batch is counted as spilled, or as failed if it could
+    /// not be written. This blocks.
+    fn spill(self: &Arc<Self>, batch: Vec<SpanData>) -> ExportResult {
+        let count = batch.len() as u64;
+        let result = self.write(batch);
+        if result.is_ok() {
+            self.stats.record_spilled(count);
+        } else {
+            self.stats.record(count, false);
+        }
+        result
+    }
+
+    fn write(self: &Arc<Self>, batch: Vec<SpanData>) -> ExportResult {
+        self.load().map_err(io_error)?;
+        let contents =
+            otlp_json::encode(batch).map_err(|error| TraceError::Other(Box::new(error)))?;
+        let size = contents.len() as u64;
+
+        // The space and file name are reserved first, so that the lock is
+        // not held while writing.
+        let path = {
+            let mut spilled = self.spilled.lock().unwrap();
+            if spilled.size + size > self.settings.max_size {
+                return Err(TraceError::from("the retry queue is full"));
+            }
+            let path = self.settings.directory.join(format!(
+                "{FILE_PREFIX}.{}.{FILE_EXTENSION}",
+                spilled.next_sequence_number
+            ));
+            spilled.next_sequence_number += 1;
+            spilled.size += size;
+            path
+        };
+        if let Err(error) =
+            fs::create_dir_all(&self.settings.directory).and_then(|()| fs::write(&path, contents))
+        {
+            self.spilled.lock().unwrap().size -= size;
+            return Err(io_error(error));
+        }
+
+        let mut spilled = self.spilled.lock().unwrap();
+        spilled.files.push_back(SpilledFile { path, size });
+        // Outside a runtime, e.g. if the pipeline is being dropped, the batch
+        // is left for the next process.
+        if spilled.retrying.is_none() {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                spilled.retrying = Some(runtime.spawn(self.clone().retry()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends spilled batches, oldest first, until there are none left,
+    /// backing off whenever the endpoint is unavailable.
+    async fn retry(self: Arc<Self>) {
+        let mut backoff = self.settings.initial_backoff;
+        loop {
+            tokio::time::sleep(backoff).await;
+            match self.send_spilled().await {
+                Progress::Finished => return,
+                Progress::Sent => backoff = self.settings.initial_backoff,
+                Progress::Failed => backoff = (backoff * 2).min(self.settings.max_backoff),
+            }
+        }
+    }
+
+    /// Sends spilled batches until there are none left, or one fails.
+    async fn send_spilled(&self) -> Progress {
+        let mut progress = Progress::Failed;
+        loop {
+            let path = {
+                let mut spilled = self.spilled.lock().unwrap();
+                let Some(file) = spilled.files.front() else {
+                    // We are the retrying task, and we are done.
+                    spilled.retrying = None;
+                    return Progress::Finished;
+                };
+                file.path.clone()
+            };
+
+            let read = {
+                let path = path.clone();
+                run_blocking(move || {
+                    let contents = fs::read_to_string(path).map_err(io_error)?;
+                    otlp_json::decode(&contents)
+                })
+            };
+            match read.await {
+                Ok(batch) => {
+                    let count = batch.len() as u64;
+                    let export = self.inner.lock().unwrap().export(batch);
+                    if export.await.is_err() {
+                        return progress;
+                    }
+                    self.stats.record(count, true);
+                }
+                // The file is unreadable, so retrying it will not help.
+                Err(error) => opentelemetry::global::handle_error(error),
+            }
+
+            if let Err(error) = run_blocking(move || fs::remove_file(path).map_err(io_error)).await
+            {
+                opentelemetry::global::handle_error(error);
+            }
+            let mut spilled = self.spilled.lock().unwrap();
+            if let Some(file) = spilled.files.pop_front() {
+                spilled.size -= file.size;
+            }
+            progress = Progress::Sent;
+        }
+    }
+}
+
+/// How far [`Shared::send_spilled`] got.
+enum Progress {
+    /// Everything was sent.
+    Finished,
+    /// Some batches were sent before one failed.
+    Sent,
+    /// The first batch failed.
+    Failed,
+}
+
+/// Parses the sequence number from a file name, if the file is a spilled
+/// batch.
+fn sequence_number(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix(FILE_PREFIX)?
+        .strip_prefix('.')?
+        .strip_suffix(FILE_EXTENSION)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+/// Runs file I/O on the blocking thread pool, so that slow disks do not hold
+/// up other tasks.
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, TraceError> + Send + 'static,
+) -> Result<T, TraceError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|error| TraceError::Other(Box::new(error)))?
+}
+
+fn io_error(error: io::Error) -> TraceError {
+    TraceError::Other(Box::new(error))
+}
@@ -0,0 +1,250 @@
+//! Counts the spans passing through an exporter.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use opentelemetry::metrics::{Counter, Meter, Unit};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+
+/// Counters for the spans handled by an exporter.
+///
+/// If created with [`ExportStats::with_meter`], the counters are also
+/// published as metrics:
+///
+///   * `ddn_tracing.spans.exported`
+///   * `ddn_tracing.spans.failed`
+///   * `ddn_tracing.spans.dropped`
+///   * `ddn_tracing.spans.spilled`
+///
+/// A clone of this will share the underlying counters.
+#[derive(Clone, Debug, Default)]
+pub struct ExportStats {
+    inner: Arc<ExportStatsInner>,
+}
+
+#[derive(Debug, Default)]
+struct ExportStatsInner {
+    exported: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+    metrics: Option<Metrics>,
+}
+
+#[derive(Debug)]
+struct Metrics {
+    exported: Counter<u64>,
+    failed: Counter<u64>,
+    dropped: Counter<u64>,
+    spilled: Counter<u64>,
+}
+
+impl ExportStats {
+    /// Creates counters which are also published as metrics, using the given
+    /// meter.
+    pub fn with_meter(meter: &Meter) -> Self {
+        let counter = |name: &'static str, description: &'static str| {
+            meter
+                .u64_counter(name)
+                .with_description(description)
+                .with_unit(Unit::new("{span}"))
+                .init()
+        };
+        let metrics = Metrics {
+            exported: counter(
+                "ddn_tracing.spans.exported",
+                "The number of spans exported successfully.",
+            ),
+            failed: counter(
+                "ddn_tracing.spans.failed",
+                "The number of spans which could not be exported.",
+            ),
+            dropped: counter(
+                "ddn_tracing.spans.dropped",
+                "The number of spans dropped because the export queue was full.",
+            ),
+            spilled: counter(
+                "ddn_tracing.spans.spilled",
+                "The number of spans written to a retry queue to be exported later.",
+            ),
+        };
+        Self {
+            inner: Arc::new(ExportStatsInner {
+                metrics: Some(metrics),
+                ..ExportStatsInner::default()
+            }),
+        }
+    }
+
+    /// The number of spans exported successfully.
+    pub fn exported(&self) -> u64 {
+        self.inner.exported.load(Ordering::Relaxed)
+    }
+
+    /// The number of spans which could not be exported.
+    pub fn failed(&self) -> u64 {
+        self.inner.failed.load(Ordering::Relaxed)
+    }
+
+    /// The number of spans dropped before export because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of spans written to a retry queue, because they could not be
+    /// exported straight away. These are counted as exported or failed once
+    /// they have been retried, unless that happens in a later process.
+    pub fn spilled(&self) -> u64 {
+        self.inner.spilled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record(&self, count: u64, succeeded: bool) {
+        let (counter, metric) = if succeeded {
+            (
+                &self.inner.exported,
+                self.inner.metrics.as_ref().map(|metrics| &metrics.exported),
+            )
+        } else {
+            (
+                &self.inner.failed,
+                self.inner.metrics.as_ref().map(|metrics| &metrics.failed),
+            )
+        };
+        counter.fetch_add(count, Ordering::Relaxed);
+        if let Some(metric) = metric {
+            metric.add(count, &[]);
+        }
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.dropped.add(1, &[]);
+        }
+    }
+
+    pub(crate) fn record_spilled(&self, count: u64) {
+        self.inner.spilled.fetch_add(count, Ordering::Relaxed);
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.spilled.add(count, &[]);
+        }
+    }
+}
+
+/// Tracks how many spans are queued for, or being sent to, one exporter.
+///
+/// A clone of this will share the underlying count.
+#[derive(Clone, Debug)]
+pub(crate) struct ExportQueue {
+    length: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl ExportQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            length: Arc::new(AtomicUsize::new(0)),
+            capacity,
+        }
+    }
+
+    /// Reserves space for a span, returning `false` if the queue is full.
+    pub fn try_push(&self) -> bool {
+        self.length
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |length| {
+                (length < self.capacity).then_some(length + 1)
+            })
+            .is_ok()
+    }
+
+    /// Frees space once spans have been exported, successfully or not.
+    fn pop(&self, count: usize) {
+        self.length.fetch_sub(count, Ordering::AcqRel);
+    }
+}
+
+/// A batch which is being exported.
+///
+/// The batch span processor drops the export future if it takes longer than
+/// its timeout, so if this is dropped before it is finished, the batch is
+/// counted as failed. Either way, it is removed from the queue.
+struct PendingExport {
+    count: usize,
+    /// Where to count the batch, unless the exporter counts it itself.
+    stats: Option<ExportStats>,
+    queue: ExportQueue,
+}
+
+impl PendingExport {
+    fn finish(mut self, succeeded: bool) {
+        if let Some(stats) = &self.stats {
+            stats.record(self.count as u64, succeeded);
+        }
+        self.queue.pop(self.count);
+        self.count = 0;
+    }
+}
+
+impl Drop for PendingExport {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            if let Some(stats) = &self.stats {
+                stats.record(self.count as u64, false);
+            }
+            self.queue.pop(self.count);
+        }
+    }
+}
+
+/// Wraps an exporter, counting the spans it exports in [`ExportStats`], and
+/// removing them from its [`ExportQueue`] once they have been sent.
+///
+/// Exporters which count their own spans, such as [`super::RetryExporter`],
+/// are given no stats here.
+#[derive(Debug)]
+pub(crate) struct StatsExporter {
+    inner: Box<dyn SpanExporter>,
+    stats: Option<ExportStats>,
+    queue: ExportQueue,
+}
+
+impl StatsExporter {
+    pub fn new(
+        inner: Box<dyn SpanExporter>,
+        stats: Option<ExportStats>,
+        queue: ExportQueue,
+    ) -> Self {
+        Self {
+            inner,
+            stats,
+            queue,
+        }
+    }
+}
+
+impl SpanExporter for StatsExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        // This is created outside the future, so that the batch is accounted
+        // for even if the future is never polled.
+        let pending = PendingExport {
+            count: batch.len(),
+            stats: self.stats.clone(),
+            queue: self.queue.clone(),
+        };
+        let export = self.inner.export(batch);
+        Box::pin(async move {
+            let result = export.await;
+            pending.finish(result.is_ok());
+            result
+        })
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.inner.force_flush()
+    }
+}
@@ -1,8 +1,15 @@
+pub mod event;
+pub mod export;
 pub mod http_server;
+pub mod resource;
 pub mod setup;
 
+mod otel_data;
+
 /// An older API, provided for compatibility.
 pub mod old;
 
+pub use ddn_tracing_macros::traced;
+
 // Re-export [`tracing`] so clients don't have to add it separately.
 pub use tracing;
@@ -2,23 +2,30 @@
 //!
 //! This module contains a wrapper around [`http::Response<T>`] that implements [`Traceable`].
 //!
+//! The request's method and path are recorded as span attributes under the same names as in
+//! [`crate::http_server::layer`], along with the route and the response's status. The query is
+//! left out, as it can carry tokens or other secrets.
+//!
 //! # Example:
 //! ```
-//! use tracing_util::{SpanVisibility, TraceableHttpResponse};
+//! use ddn_tracing::old::{HttpRequestInfo, SpanVisibility, TraceableHttpResponse};
 //! use axum::{http::Request, middleware::Next};
 //!
 //! async fn graphql_request_tracing_middleware<B: Send>(
 //!     request: Request<B>,
 //!     next: Next<B>,
 //! ) -> axum::response::Result<axum::response::Response> {
-//!     let tracer = tracing_util::global_tracer();
-//!     let path = "/graphql";
+//!     let tracer = ddn_tracing::old::global_tracer();
+//!     // The span is named after the method, and also the matched route if this is added with
+//!     // `Router::route_layer` and the `axum` feature is enabled.
+//!     let request_info = HttpRequestInfo::new(&request);
+//!     let span_name = request_info.span_name();
 //!
 //!     Ok(tracer
-//!        .in_span_async(path, path.to_string(), SpanVisibility::User, || {
+//!        .in_span_async(span_name.clone(), span_name, SpanVisibility::User, || {
 //!            Box::pin(async move {
 //!                let response = next.run(request).await;
-//!                TraceableHttpResponse::new(response, path)
+//!                TraceableHttpResponse::for_request(response, request_info)
 //!            })
 //!        })
 //!        .await
@@ -26,36 +33,173 @@
 //! }
 //! ```
 
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use opentelemetry::KeyValue;
+use opentelemetry_semantic_conventions as semcov;
+
 use super::traceable::{ErrorVisibility, Traceable, TraceableError};
 
 /// Wrapper around `http::Response<T>` that is traceable in spans.
 ///
+/// The response is an error unless its status is successful, which by default means a 2xx or
+/// 3xx status. This can be changed with [`TraceableHttpResponse::with_success_classifier`].
+///
 /// # Example:
 /// ```
 /// use axum::response::{Response, IntoResponse};
 /// let response: Response = "hello world!".into_response();
-/// tracing_util::TraceableHttpResponse::new(response, "/create_user");
+/// ddn_tracing::old::TraceableHttpResponse::new(response, "/create_user");
 /// ```
 pub struct TraceableHttpResponse<T> {
     /// The HTTP response.
     pub response: http::Response<T>,
     /// Path of the request that generated this response.
-    pub path: &'static str,
+    pub path: Cow<'static, str>,
+    request: Option<HttpRequestInfo>,
+    is_success: Arc<dyn Fn(http::StatusCode) -> bool + Send + Sync>,
 }
 
 impl<T> TraceableHttpResponse<T> {
     /// Creates a new `TraceableHttpResponse`.
-    pub fn new(response: http::Response<T>, path: &'static str) -> Self {
-        Self { response, path }
+    pub fn new(response: http::Response<T>, path: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            response,
+            path: path.into(),
+            request: None,
+            is_success: Arc::new(is_success_or_redirection),
+        }
+    }
+
+    /// Creates a new `TraceableHttpResponse` for the given request, whose method, path and route
+    /// are recorded on the span along with the status.
+    pub fn for_request(response: http::Response<T>, request: HttpRequestInfo) -> Self {
+        Self {
+            path: request.path.clone().into(),
+            request: Some(request),
+            ..Self::new(response, "")
+        }
+    }
+
+    /// Sets the function which decides whether a status is successful. It can capture
+    /// configuration, such as the statuses which are expected for a route.
+    ///
+    /// # Example:
+    /// ```
+    /// use axum::http::StatusCode;
+    /// use axum::response::{Response, IntoResponse};
+    /// let response: Response = StatusCode::NOT_FOUND.into_response();
+    /// // A missing resource is expected, and so is not an error.
+    /// ddn_tracing::old::TraceableHttpResponse::new(response, "/users")
+    ///     .with_success_classifier(|status| status.is_success() || status == StatusCode::NOT_FOUND);
+    /// ```
+    #[must_use]
+    pub fn with_success_classifier(
+        mut self,
+        is_success: impl Fn(http::StatusCode) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_success = Arc::new(is_success);
+        self
+    }
+}
+
+/// The parts of an HTTP request which are recorded on the span of its response.
+///
+/// This should be taken from the request before it is handled, and passed to
+/// [`TraceableHttpResponse::for_request`] with the response.
+#[derive(Clone, Debug)]
+pub struct HttpRequestInfo {
+    /// The request method.
+    pub method: http::Method,
+    /// The path of the request target, without the query.
+    pub path: String,
+    /// The route template which matched the request, such as `/users/:id`, if known.
+    pub route: Option<String>,
+}
+
+impl HttpRequestInfo {
+    /// Takes the method and path from a request. The route is taken from axum's
+    /// `MatchedPath` if the `axum` feature is enabled and the request has been routed, i.e. in a
+    /// middleware added with `Router::route_layer`.
+    pub fn new<B>(request: &http::Request<B>) -> Self {
+        #[cfg(feature = "axum")]
+        let route = request
+            .extensions()
+            .get::<axum::extract::MatchedPath>()
+            .map(|matched_path| matched_path.as_str().to_owned());
+        #[cfg(not(feature = "axum"))]
+        let route = None;
+
+        Self {
+            method: request.method().clone(),
+            path: request.uri().path().to_owned(),
+            route,
+        }
+    }
+
+    /// A span name for the request, made up of the method and the route, or just the method if
+    /// there is no route, following the OpenTelemetry semantic conventions. The path is not used,
+    /// as it can contain identifiers, which would make span names unbounded.
+    pub fn span_name(&self) -> String {
+        match &self.route {
+            Some(route) => format!("{} {route}", self.method),
+            None => self.method.to_string(),
+        }
+    }
+}
+
+/// The default success classifier, which accepts 2xx and 3xx statuses.
+fn is_success_or_redirection(status: http::StatusCode) -> bool {
+    status.is_success() || status.is_redirection()
+}
+
+/// The kind of an unsuccessful HTTP response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum ResponseErrorKind {
+    /// A 4xx status.
+    #[display(fmt = "client error")]
+    Client,
+    /// A 5xx status.
+    #[display(fmt = "server error")]
+    Server,
+    /// Any other status which was classified as unsuccessful.
+    #[display(fmt = "unexpected status")]
+    Other,
+}
+
+impl ResponseErrorKind {
+    fn of(status: http::StatusCode) -> Self {
+        if status.is_client_error() {
+            Self::Client
+        } else if status.is_server_error() {
+            Self::Server
+        } else {
+            Self::Other
+        }
     }
 }
 
 /// Error type for `TraceableHttpResponse`.
 /// Only used as an associated type when implementing [`Traceable`] trait for [`TraceableHttpResponse`].
 #[derive(Debug, derive_more::Display)]
-#[display(fmt = "{error}")]
+#[display(fmt = "HTTP request to {path} failed with {kind} {status}")]
 pub struct ResponseError {
-    error: String,
+    path: String,
+    status: http::StatusCode,
+    kind: ResponseErrorKind,
+}
+
+impl ResponseError {
+    /// The status of the response.
+    pub fn status(&self) -> http::StatusCode {
+        self.status
+    }
+
+    /// Whether this was a client or server error.
+    pub fn kind(&self) -> ResponseErrorKind {
+        self.kind
+    }
 }
 
 impl TraceableError for ResponseError {
@@ -70,17 +214,32 @@ impl<T> Traceable for TraceableHttpResponse<T> {
     type ErrorType<'a> = ResponseError where T: 'a;
 
     fn get_error(&self) -> Option<Self::ErrorType<'_>> {
-        // If the response status is not OK, return an error.
-        if self.response.status() == http::StatusCode::OK {
+        let status = self.response.status();
+        if (self.is_success)(status) {
             None
         } else {
             Some(ResponseError {
-                error: format!(
-                    "HTTP request to {} failed with status {}",
-                    self.path,
-                    self.response.status()
-                ),
+                path: self.path.to_string(),
+                status,
+                kind: ResponseErrorKind::of(status),
             })
         }
     }
+
+    fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = vec![KeyValue::new(
+            semcov::trace::HTTP_RESPONSE_STATUS_CODE,
+            i64::from(self.response.status().as_u16()),
+        )];
+        if let Some(request) = &self.request {
+            // These match the fields of the `http_server` layer's spans, except that the query is
+            // left out of the URI.
+            attributes.push(KeyValue::new("method", request.method.to_string()));
+            attributes.push(KeyValue::new("uri", request.path.clone()));
+            if let Some(route) = &request.route {
+                attributes.push(KeyValue::new(semcov::trace::HTTP_ROUTE, route.clone()));
+            }
+        }
+        attributes
+    }
 }
@@ -5,13 +5,17 @@ mod traceable;
 mod tracer;
 
 // Avoid conflicts with `http` crate
-pub use http::TraceableHttpResponse;
+pub use http::{HttpRequestInfo, ResponseError, ResponseErrorKind, TraceableHttpResponse};
 pub use request::get_trace_headers;
 pub use setup::{shutdown_tracer, start_tracer};
 pub use traceable::{ErrorVisibility, Traceable, TraceableError};
+// The derive macro for `TraceableError`, which shares its name.
+pub use ddn_tracing_macros::TraceableError;
+pub(crate) use tracer::SPAN_TARGET;
 pub use tracer::{
-    add_event_on_active_span, get_trace_context, global_tracer, set_attribute_on_active_span,
-    set_status_on_current_span, AttributeVisibility, SpanVisibility,
+    add_event_on_active_span, add_event_with_attributes_on_active_span, get_trace_context,
+    global_tracer, set_attribute_on_active_span, set_status_on_current_span, AttributeVisibility,
+    SpanVisibility, TraceableFutureExt, TracedFuture, Tracer,
 };
 
 // re-export things from OpenTelemetry to avoid library users importing their own version and
@@ -5,8 +5,9 @@
 pub fn get_trace_headers() -> http::HeaderMap {
     let mut headers_map = http::HeaderMap::new();
     let mut header_injector = opentelemetry_http::HeaderInjector(&mut headers_map);
+    let context = super::tracer::current_context();
     opentelemetry::global::get_text_map_propagator(|propagator| {
-        propagator.inject(&mut header_injector);
+        propagator.inject_context(&context, &mut header_injector);
     });
     headers_map
 }
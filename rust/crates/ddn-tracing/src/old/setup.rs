@@ -1,24 +1,32 @@
-use std::error::Error;
-use std::mem::ManuallyDrop;
+use std::borrow::Cow;
+use std::sync::Mutex;
 
 use super::global_tracer;
 use super::tracer::Tracer;
+use crate::setup::GlobalTracing;
+
+/// The tracing setup installed by `start_tracer`.
+///
+/// We do not drop this immediately. This is handled by `shutdown_tracer`
+/// instead.
+static GLOBAL_TRACING: Mutex<Option<GlobalTracing>> = Mutex::new(None);
 
 pub fn start_tracer(
     endpoint: Option<&str>,
-    service_name: &'static str,
-    service_version: &'static str,
-) -> Result<Tracer, Box<dyn Error + Send + Sync>> {
-    // Do not drop the global tracing provider immediately.
-    // This is handled by `shutdown_tracer` instead.
-    let _ = ManuallyDrop::new(crate::setup::init_tracing(
-        endpoint,
-        service_name,
-        service_version,
-    )?);
+    service_name: impl Into<Cow<'static, str>>,
+    service_version: impl Into<Cow<'static, str>>,
+) -> Result<Tracer, crate::setup::SetupError> {
+    let global_tracing = crate::setup::init_tracing(endpoint, service_name, service_version)?;
+    *GLOBAL_TRACING.lock().unwrap() = Some(global_tracing);
     Ok(global_tracer())
 }
 
+/// Unregisters the global tracing provider, waiting a bounded amount of time
+/// for pending spans to be exported.
 pub fn shutdown_tracer() {
-    opentelemetry::global::shutdown_tracer_provider();
+    let global_tracing = GLOBAL_TRACING.lock().unwrap().take();
+    if global_tracing.is_none() {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+    drop(global_tracing);
 }
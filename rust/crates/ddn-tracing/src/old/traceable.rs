@@ -1,3 +1,7 @@
+use std::backtrace::Backtrace;
+
+use opentelemetry::KeyValue;
+
 pub enum ErrorVisibility {
     Internal,
     User,
@@ -13,6 +17,21 @@ pub trait TraceableError: core::fmt::Display + core::fmt::Debug {
     fn details(&self) -> String {
         format!("{self:?}")
     }
+
+    /// The name of the error type, recorded as the exception type, if it is known.
+    fn type_name(&self) -> Option<&'static str> {
+        Some(std::any::type_name::<Self>())
+    }
+
+    /// This as a standard error, if it is one, so that its chain of sources can be recorded.
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+
+    /// The backtrace captured when the error was created, if any.
+    fn backtrace(&self) -> Option<&Backtrace> {
+        None
+    }
 }
 
 // `Infallible` has no inhabitants, so will never occur. This means that
@@ -24,11 +43,94 @@ impl TraceableError for std::convert::Infallible {
     }
 }
 
+/// Boxed errors are assumed to be internal, as nothing is known about them, not even their type.
+impl TraceableError for Box<dyn std::error::Error> {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    // The name of the boxed type is not known.
+    fn type_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self)
+    }
+}
+
+impl TraceableError for Box<dyn std::error::Error + Send> {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    // The name of the boxed type is not known.
+    fn type_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self)
+    }
+}
+
+impl TraceableError for Box<dyn std::error::Error + Send + Sync> {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    // The name of the boxed type is not known.
+    fn type_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&**self)
+    }
+}
+
+#[cfg(feature = "io")]
+impl TraceableError for std::io::Error {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl TraceableError for anyhow::Error {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    // The name of the boxed type is not known.
+    fn type_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.as_ref())
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        Some(anyhow::Error::backtrace(self))
+    }
+}
+
 pub trait Traceable {
     type ErrorType<'a>: TraceableError
     where
         Self: 'a;
     fn get_error(&self) -> Option<Self::ErrorType<'_>>;
+
+    /// Attributes describing the result, which are recorded on the span whether or not it is an
+    /// error.
+    fn attributes(&self) -> Vec<KeyValue> {
+        Vec::new()
+    }
 }
 
 /// A helper type to wrap a reference to `E` from [`Result<T, E>`].
@@ -48,6 +150,26 @@ impl<'e, E: TraceableError> TraceableError for ResultError<'e, E> {
     fn visibility(&self) -> ErrorVisibility {
         self.error.visibility()
     }
+
+    fn description(&self) -> String {
+        self.error.description()
+    }
+
+    fn details(&self) -> String {
+        self.error.details()
+    }
+
+    fn type_name(&self) -> Option<&'static str> {
+        self.error.type_name()
+    }
+
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.as_error()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.error.backtrace()
+    }
 }
 
 impl<R, E> Traceable for Result<R, E>
@@ -61,3 +183,30 @@ where
         self.as_ref().err().map(|e| ResultError { error: e })
     }
 }
+
+// These never represent an error, and so are always traceable.
+impl Traceable for () {
+    type ErrorType<'a> = std::convert::Infallible;
+
+    fn get_error(&self) -> Option<Self::ErrorType<'_>> {
+        None
+    }
+}
+
+impl<T> Traceable for Option<T> {
+    type ErrorType<'a> = std::convert::Infallible
+        where T: 'a;
+
+    fn get_error(&self) -> Option<Self::ErrorType<'_>> {
+        None
+    }
+}
+
+impl<T> Traceable for Vec<T> {
+    type ErrorType<'a> = std::convert::Infallible
+        where T: 'a;
+
+    fn get_error(&self) -> Option<Self::ErrorType<'_>> {
+        None
+    }
+}
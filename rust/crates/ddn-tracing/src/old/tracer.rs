@@ -1,17 +1,22 @@
+use std::backtrace::BacktraceStatus;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::task::{self, ready, Poll};
+use std::time::SystemTime;
 
 use http::HeaderMap;
-use opentelemetry::global::{self, BoxedTracer};
-use opentelemetry::trace::{
-    get_active_span, FutureExt, SpanRef, TraceContextExt, Tracer as OtelTracer,
-};
-use opentelemetry::{Context, Key};
+use opentelemetry::global;
+use opentelemetry::trace::{get_active_span, Event, SpanBuilder, SpanRef, TraceContextExt};
+use opentelemetry::{Context, Key, KeyValue, StringValue, Value};
 use opentelemetry_http::HeaderExtractor;
+use pin_project_lite::pin_project;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use super::traceable::{ErrorVisibility, Traceable, TraceableError};
+use crate::export::INTERNAL_ATTRIBUTE_PREFIX;
+use crate::otel_data::with_otel_data;
 
 #[derive(Clone, Copy, derive_more::Display)]
 pub enum SpanVisibility {
@@ -27,16 +32,76 @@ pub enum AttributeVisibility {
     Internal,
 }
 
+/// The span which the `*_on_active_span` functions act on.
+enum ActiveSpan<'a> {
+    /// A `tracing` span, which is exported through `tracing_opentelemetry`.
+    Tracing(&'a mut SpanBuilder),
+    /// A span created directly through OpenTelemetry.
+    OpenTelemetry(&'a SpanRef<'a>),
+}
+
+impl ActiveSpan<'_> {
+    fn set_attribute(&mut self, attribute: KeyValue) {
+        match self {
+            Self::Tracing(builder) => builder
+                .attributes
+                .get_or_insert_with(Vec::new)
+                .push(attribute),
+            Self::OpenTelemetry(span) => span.set_attribute(attribute),
+        }
+    }
+
+    fn set_status(&mut self, status: opentelemetry::trace::Status) {
+        match self {
+            Self::Tracing(builder) => builder.status = status,
+            Self::OpenTelemetry(span) => span.set_status(status),
+        }
+    }
+
+    fn add_event(&mut self, event: Event) {
+        match self {
+            Self::Tracing(builder) => builder.events.get_or_insert_with(Vec::new).push(event),
+            Self::OpenTelemetry(span) => {
+                span.add_event_with_timestamp(event.name, event.timestamp, event.attributes);
+            }
+        }
+    }
+}
+
+/// Runs `f` on the current `tracing` span if it is being exported, or on the active OpenTelemetry
+/// span otherwise.
+fn with_active_span(f: impl FnOnce(&mut ActiveSpan)) {
+    let mut f = Some(f);
+    with_otel_data(&tracing::Span::current(), |otel_data| {
+        if let Some(f) = f.take() {
+            f(&mut ActiveSpan::Tracing(&mut otel_data.builder));
+        }
+    });
+    if let Some(f) = f {
+        get_active_span(|span| f(&mut ActiveSpan::OpenTelemetry(&span)));
+    }
+}
+
+/// The context of the current `tracing` span if it is being exported, or the active OpenTelemetry
+/// context otherwise.
+pub(super) fn current_context() -> Context {
+    let span = tracing::Span::current();
+    match with_otel_data(&span, |_| ()) {
+        Some(()) => span.context(),
+        None => Context::current(),
+    }
+}
+
 pub fn set_status_on_current_span<R>(result: &R)
 where
     R: Traceable,
 {
-    get_active_span(|span| {
-        set_span_attributes(&span, SpanVisibility::User, result);
+    with_active_span(|span| {
+        set_span_attributes(span, SpanVisibility::User, result);
     });
 }
 
-fn set_span_attributes<R>(span: &SpanRef, visibility: SpanVisibility, result: &R)
+fn set_span_attributes<R>(span: &mut ActiveSpan, visibility: SpanVisibility, result: &R)
 where
     R: Traceable,
 {
@@ -46,6 +111,16 @@ where
         "visibility",
         visibility.to_string(),
     );
+    set_result_attributes(span, visibility, result);
+}
+
+fn set_result_attributes<R>(span: &mut ActiveSpan, visibility: SpanVisibility, result: &R)
+where
+    R: Traceable,
+{
+    for attribute in result.attributes() {
+        span.set_attribute(attribute);
+    }
 
     if let Some(e) = result.get_error() {
         let is_private_error = matches!(e.visibility(), ErrorVisibility::Internal)
@@ -72,23 +147,76 @@ where
             "error_details",
             e.details(),
         );
+
+        span.add_event(exception_event(&e, is_private_error));
     }
 }
 
+/// Builds an `exception` event for the error, following the OpenTelemetry semantic conventions.
+///
+/// The chain of sources is recorded as the stack trace, as `tracing_opentelemetry` does for
+/// errors recorded through `tracing`. The type, chain and backtrace are internal if the error is
+/// private.
+fn exception_event(error: &impl TraceableError, is_private_error: bool) -> Event {
+    let detail_visibility = if is_private_error {
+        AttributeVisibility::Internal
+    } else {
+        AttributeVisibility::Default
+    };
+    let message = if is_private_error {
+        "Internal error".to_owned()
+    } else {
+        error.description()
+    };
+    let mut attributes = vec![KeyValue::new("exception.message", message)];
+    if let Some(type_name) = error.type_name() {
+        attributes.push(KeyValue::new(
+            key_with_visibility(detail_visibility, "exception.type"),
+            type_name,
+        ));
+    }
+
+    let mut chain = Vec::<StringValue>::new();
+    let mut next = error.as_error().and_then(std::error::Error::source);
+    while let Some(source) = next {
+        chain.push(source.to_string().into());
+        next = source.source();
+    }
+    if !chain.is_empty() {
+        attributes.push(KeyValue::new(
+            key_with_visibility(detail_visibility, "exception.stacktrace"),
+            Value::Array(chain.into()),
+        ));
+    }
+    if let Some(backtrace) = error
+        .backtrace()
+        .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+    {
+        attributes.push(KeyValue::new(
+            key_with_visibility(detail_visibility, "exception.backtrace"),
+            backtrace.to_string(),
+        ));
+    }
+
+    Event::new("exception", SystemTime::now(), attributes, 0)
+}
+
 fn set_attribute_on_span<V>(
-    span: &SpanRef,
+    span: &mut ActiveSpan,
     visibility: AttributeVisibility,
     key: &'static str,
     value: V,
 ) where
     V: Into<opentelemetry::Value>,
 {
-    let key_with_visibility: Key = match visibility {
-        AttributeVisibility::Default => key.into(),
-        AttributeVisibility::Internal => format!("internal.{key}").into(),
-    };
+    span.set_attribute(KeyValue::new(key_with_visibility(visibility, key), value));
+}
 
-    span.set_attribute(opentelemetry::KeyValue::new(key_with_visibility, value));
+fn key_with_visibility(visibility: AttributeVisibility, key: &'static str) -> Key {
+    match visibility {
+        AttributeVisibility::Default => key.into(),
+        AttributeVisibility::Internal => format!("{INTERNAL_ATTRIBUTE_PREFIX}{key}").into(),
+    }
 }
 
 /// Sets an attribute on the active span, prefixing the `key` with `internal.` if `visibility` is `Internal`.
@@ -96,24 +224,45 @@ pub fn set_attribute_on_active_span<V>(visibility: AttributeVisibility, key: &'s
 where
     V: Into<opentelemetry::Value>,
 {
-    get_active_span(|span| set_attribute_on_span(&span, visibility, key, value));
+    with_active_span(|span| set_attribute_on_span(span, visibility, key, value));
 }
 
 /// Adds an event on the active span, with the given `name` and no attributes.
-// TODO: Add support for attributes
 pub fn add_event_on_active_span(name: String) {
-    get_active_span(|span| span.add_event(name, vec![]));
+    add_event_with_attributes_on_active_span(name, [], None);
 }
 
-/// Wrapper around the OpenTelemetry tracer. Used for providing convenience methods to add spans.
-pub struct Tracer {
-    tracer: BoxedTracer,
+/// Adds an event on the active span, with the given `name` and attributes. Each attribute key is
+/// prefixed with `internal.` if its visibility is `Internal`.
+///
+/// The event is timestamped with `timestamp` if given, or the current time otherwise.
+pub fn add_event_with_attributes_on_active_span<A>(
+    name: String,
+    attributes: A,
+    timestamp: Option<SystemTime>,
+) where
+    A: IntoIterator<Item = (AttributeVisibility, &'static str, opentelemetry::Value)>,
+{
+    let attributes = attributes
+        .into_iter()
+        .map(|(visibility, key, value)| KeyValue::new(key_with_visibility(visibility, key), value))
+        .collect();
+    let event = Event::new(
+        name,
+        timestamp.unwrap_or_else(SystemTime::now),
+        attributes,
+        0,
+    );
+    with_active_span(|span| span.add_event(event));
 }
 
-impl Tracer {
-    pub(crate) fn new(tracer: BoxedTracer) -> Self {
-        Self { tracer }
-    }
+/// Convenience methods to add spans.
+///
+/// Spans are created through `tracing`, and so share one span tree with spans created with
+/// `tracing` directly. They are sent to the current `tracing` subscriber.
+#[derive(Clone, Copy, Debug)]
+pub struct Tracer {
+    _private: (),
 }
 
 impl Tracer {
@@ -121,8 +270,8 @@ impl Tracer {
     /// on the span based on `visibility` and sets the span's error attributes based on the result of the closure.
     pub fn in_span<R, F>(
         &self,
-        name: &'static str,
-        display_name: Cow<'static, str>,
+        name: impl Into<Cow<'static, str>>,
+        display_name: impl Into<Cow<'static, str>>,
         visibility: SpanVisibility,
         f: F,
     ) -> R
@@ -130,25 +279,18 @@ impl Tracer {
         F: FnOnce() -> R,
         R: Traceable,
     {
-        self.tracer.in_span(name, |cx| {
-            let result = f();
-            set_attribute_on_span(
-                &cx.span(),
-                AttributeVisibility::Default,
-                "display.name",
-                display_name,
-            );
-            set_span_attributes(&cx.span(), visibility, &result);
-            result
-        })
+        let span = new_span(name, display_name, visibility);
+        let result = span.in_scope(f);
+        record_result(&span, visibility, &result);
+        result
     }
 
     /// Runs the given closure `f` asynchronously in a new span with the given `name`, and sets a visibility attribute
     /// on the span based on `visibility` and sets the span's error attributes based on the result of the closure.
     pub async fn in_span_async<'a, R, F>(
-        &'a self,
-        name: &'static str,
-        display_name: String,
+        &self,
+        name: impl Into<Cow<'static, str>>,
+        display_name: impl Into<Cow<'static, str>>,
         visibility: SpanVisibility,
         f: F,
     ) -> R
@@ -158,30 +300,38 @@ impl Tracer {
         F: FnOnce() -> Pin<Box<dyn Future<Output = R> + 'a + Send>>,
         R: Traceable,
     {
-        self.tracer
-            .in_span(name, |cx| {
-                async move {
-                    let result = f().await;
-                    get_active_span(|span| {
-                        set_attribute_on_span(
-                            &span,
-                            AttributeVisibility::Default,
-                            "display.name",
-                            display_name,
-                        );
-                        set_span_attributes(&span, visibility, &result);
-                    });
-                    result
-                }
-                .with_context(cx)
-            })
+        self.in_span_future(name, display_name, visibility, async move { f().await })
             .await
     }
 
+    /// Runs the given future `future` in a new span with the given `name`, and sets a visibility
+    /// attribute on the span based on `visibility` and sets the span's error attributes based on
+    /// the output of the future.
+    ///
+    /// Unlike `in_span_async`, this does not allocate. The span is created immediately, as a
+    /// child of the current span, and ends when the future completes or is dropped.
+    pub fn in_span_future<F>(
+        &self,
+        name: impl Into<Cow<'static, str>>,
+        display_name: impl Into<Cow<'static, str>>,
+        visibility: SpanVisibility,
+        future: F,
+    ) -> TracedFuture<F>
+    where
+        F: Future,
+        F::Output: Traceable,
+    {
+        TracedFuture {
+            inner: future,
+            span: new_span(name, display_name, visibility),
+            visibility,
+        }
+    }
+
     pub async fn in_span_async_with_parent_context<'a, R, F>(
         &'a self,
-        name: &'static str,
-        display_name: String,
+        name: impl Into<Cow<'static, str>>,
+        display_name: impl Into<Cow<'static, str>>,
         visibility: SpanVisibility,
         parent_headers: &HeaderMap<http::HeaderValue>,
         f: F,
@@ -197,20 +347,112 @@ impl Tracer {
         let parent_context_span = parent_context.span();
         let parent_context_span_context = parent_context_span.span_context();
 
+        let span = new_span(name, display_name, visibility);
         // if there is no parent span ID, we get something nonsensical, so we need to validate it
         if parent_context_span_context.is_valid() {
-            self.in_span_async(name, display_name, visibility, f)
-                .with_context(parent_context)
-                .await
-        } else {
-            self.in_span_async(name, display_name, visibility, f).await
+            span.set_parent(parent_context.clone());
+        }
+
+        TracedFuture {
+            inner: async move { f().await },
+            span,
+            visibility,
         }
+        .await
+    }
+}
+
+/// The target of spans created by [`Tracer`].
+///
+/// These are always exported, whatever `RUST_LOG` says, as they are how the
+/// engine reports its work to users. They are left out of logs, where their
+/// names would all be `span`, as `tracing` span names are static.
+pub(crate) const SPAN_TARGET: &str = "ddn_tracing::old::span";
+
+/// Creates a span, which is named `name` when exported.
+fn new_span(
+    name: impl Into<Cow<'static, str>>,
+    display_name: impl Into<Cow<'static, str>>,
+    visibility: SpanVisibility,
+) -> tracing::Span {
+    let (name, display_name) = (name.into(), display_name.into());
+    tracing::info_span!(
+        target: SPAN_TARGET,
+        "span",
+        otel.name = %name,
+        display.name = %display_name,
+        internal.visibility = %visibility,
+    )
+}
+
+/// Sets the span's error attributes based on `result`.
+fn record_result<R>(span: &tracing::Span, visibility: SpanVisibility, result: &R)
+where
+    R: Traceable,
+{
+    with_otel_data(span, |otel_data| {
+        set_result_attributes(
+            &mut ActiveSpan::Tracing(&mut otel_data.builder),
+            visibility,
+            result,
+        );
+    });
+}
+
+pin_project! {
+    /// A future which runs in a span. See [`Tracer::in_span_future`].
+    #[must_use = "futures do nothing unless polled"]
+    pub struct TracedFuture<F> {
+        #[pin]
+        inner: F,
+        span: tracing::Span,
+        visibility: SpanVisibility,
     }
 }
 
+impl<F> Future for TracedFuture<F>
+where
+    F: Future,
+    F::Output: Traceable,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, task_cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _entered = this.span.enter();
+        let result = ready!(this.inner.poll(task_cx));
+        record_result(this.span, *this.visibility, &result);
+        Poll::Ready(result)
+    }
+}
+
+/// Extension trait for running futures in a span.
+pub trait TraceableFutureExt: Future + Sized
+where
+    Self::Output: Traceable,
+{
+    /// Runs this future in a new span with the given `name`, which is also used as its display
+    /// name. See [`Tracer::in_span_future`].
+    fn traced(
+        self,
+        name: impl Into<Cow<'static, str>>,
+        visibility: SpanVisibility,
+    ) -> TracedFuture<Self> {
+        let name = name.into();
+        global_tracer().in_span_future(name.clone(), name, visibility, self)
+    }
+}
+
+impl<F> TraceableFutureExt for F
+where
+    F: Future,
+    F::Output: Traceable,
+{
+}
+
 /// Return the current trace context, useful for including it HTTP requests etc
 pub fn get_trace_context() -> HashMap<String, String> {
-    let ctx = Context::current();
+    let ctx = current_context();
     let mut trace_headers = HashMap::new();
     global::get_text_map_propagator(|propagator| {
         propagator.inject_context(&ctx, &mut trace_headers);
@@ -218,7 +460,8 @@ pub fn get_trace_context() -> HashMap<String, String> {
     trace_headers
 }
 
-/// Util for accessing the globally installed tracer
+/// Util for accessing the tracer. Spans are sent to the current `tracing` subscriber, which is
+/// usually the globally installed one.
 pub fn global_tracer() -> Tracer {
-    Tracer::new(opentelemetry::global::tracer("engine-tracing-util"))
+    Tracer { _private: () }
 }
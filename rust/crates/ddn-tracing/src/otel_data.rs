@@ -0,0 +1,22 @@
+//! Access to the OpenTelemetry data recorded on `tracing` spans.
+
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+/// Runs `f` on the OpenTelemetry data of the given span.
+///
+/// This returns `None` without running `f` if the span is disabled, or is
+/// not being exported.
+pub(crate) fn with_otel_data<R>(
+    span: &tracing::Span,
+    f: impl FnOnce(&mut OtelData) -> R,
+) -> Option<R> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(id)?;
+        let mut extensions = span.extensions_mut();
+        extensions.get_mut::<OtelData>().map(f)
+    })
+    .flatten()
+}
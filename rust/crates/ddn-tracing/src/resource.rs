@@ -0,0 +1,288 @@
+//! Detects the resource attributes describing the entity producing telemetry.
+//!
+//! These are attached to every exported span, and allow us to tell which host,
+//! process, container, or Kubernetes pod produced it.
+
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::resource::{
+    EnvResourceDetector, OsResourceDetector, Resource, ResourceDetector,
+};
+use opentelemetry_semantic_conventions as semcov;
+
+/// How long each detector may take. None of the built-in detectors make
+/// network calls, so this is only a safeguard.
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The file describing the control groups of the current process, from which
+/// we extract the container ID.
+const CGROUP_PATH: &str = "/proc/self/cgroup";
+
+/// Environment variables expected to be populated through the Kubernetes
+/// downward API.
+const K8S_POD_NAME_VAR: &str = "K8S_POD_NAME";
+const K8S_POD_UID_VAR: &str = "K8S_POD_UID";
+const K8S_NAMESPACE_NAME_VAR: &str = "K8S_NAMESPACE_NAME";
+const K8S_NODE_NAME_VAR: &str = "K8S_NODE_NAME";
+
+/// The environment variable naming the deployment environment, e.g.
+/// "staging" or "production".
+const DEPLOYMENT_ENVIRONMENT_VAR: &str = "DEPLOYMENT_ENVIRONMENT";
+
+/// Builds the resource for a service.
+///
+/// Attributes are merged in the following order, with later sources taking
+/// precedence over earlier ones:
+///
+///   1. the built-in detectors in this module, plus the OS detector,
+///   2. `OTEL_RESOURCE_ATTRIBUTES`,
+///   3. the provided service attributes, which are configured explicitly.
+pub fn build(service_attributes: impl IntoIterator<Item = KeyValue>) -> Resource {
+    let detected = Resource::from_detectors(
+        DETECTION_TIMEOUT,
+        vec![
+            Box::new(ServiceInstanceResourceDetector),
+            Box::new(HostResourceDetector),
+            Box::new(OsResourceDetector),
+            Box::new(ProcessResourceDetector),
+            Box::new(ContainerResourceDetector),
+            Box::new(KubernetesResourceDetector),
+            Box::new(DeploymentResourceDetector),
+            Box::new(EnvResourceDetector::new()),
+        ],
+    );
+    detected.merge(&Resource::new(service_attributes))
+}
+
+/// Provides a default `service.instance.id`, which is a random UUID generated
+/// once per process.
+///
+/// This can be overridden through `OTEL_RESOURCE_ATTRIBUTES` or explicitly.
+#[derive(Debug)]
+pub struct ServiceInstanceResourceDetector;
+
+impl ResourceDetector for ServiceInstanceResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+        let instance_id = INSTANCE_ID.get_or_init(|| uuid::Uuid::new_v4().to_string());
+        Resource::new(vec![KeyValue::new(
+            semcov::resource::SERVICE_INSTANCE_ID,
+            instance_id.clone(),
+        )])
+    }
+}
+
+/// Detects `host.name`.
+#[derive(Debug)]
+pub struct HostResourceDetector;
+
+impl ResourceDetector for HostResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        let host_name = nix::unistd::gethostname()
+            .ok()
+            .and_then(|name| name.into_string().ok());
+        Resource::new(host_name.map(|name| KeyValue::new(semcov::resource::HOST_NAME, name)))
+    }
+}
+
+/// Detects `process.pid`, `process.executable.name`, and `process.runtime.*`.
+///
+/// Unlike the SDK's process detector, this does not record the command-line
+/// arguments, as they may contain secrets.
+#[derive(Debug)]
+pub struct ProcessResourceDetector;
+
+impl ResourceDetector for ProcessResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        let rustc_version = env!("DDN_TRACING_RUSTC_VERSION");
+        let mut attributes = vec![
+            KeyValue::new(semcov::resource::PROCESS_PID, i64::from(std::process::id())),
+            KeyValue::new(semcov::resource::PROCESS_RUNTIME_NAME, "rustc"),
+        ];
+        // The version string looks like "rustc 1.78.0 (9b00956e5 2024-04-29)".
+        if let Some(version) = rustc_version.split_whitespace().nth(1) {
+            attributes.push(KeyValue::new(
+                semcov::resource::PROCESS_RUNTIME_VERSION,
+                version.to_owned(),
+            ));
+        }
+        if !rustc_version.is_empty() {
+            attributes.push(KeyValue::new(
+                semcov::resource::PROCESS_RUNTIME_DESCRIPTION,
+                rustc_version,
+            ));
+        }
+        if let Some(name) = env::current_exe()
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        {
+            attributes.push(KeyValue::new(
+                semcov::resource::PROCESS_EXECUTABLE_NAME,
+                name,
+            ));
+        }
+        Resource::new(attributes)
+    }
+}
+
+/// Detects `container.id` from the control groups of the current process.
+///
+/// Nothing is detected when not running inside a container, or when the
+/// container runtime does not expose the ID through `/proc/self/cgroup`.
+#[derive(Debug)]
+pub struct ContainerResourceDetector;
+
+impl ResourceDetector for ContainerResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        let container_id = fs::read_to_string(CGROUP_PATH)
+            .ok()
+            .and_then(|contents| parse_container_id(&contents));
+        Resource::new(container_id.map(|id| KeyValue::new(semcov::resource::CONTAINER_ID, id)))
+    }
+}
+
+/// Finds a container ID in the contents of a cgroup file.
+///
+/// Each line looks like one of the following, depending on the cgroup version
+/// and the container runtime:
+///
+///   * `12:memory:/docker/<id>`
+///   * `11:cpu:/kubepods/burstable/pod<uuid>/<id>`
+///   * `0::/system.slice/docker-<id>.scope`
+///   * `0::/kubepods.slice/.../cri-containerd-<id>.scope`
+///
+/// The ID itself is always 64 hexadecimal characters. Returns `None` if no
+/// line contains one, e.g. when not running inside a container.
+fn parse_container_id(contents: &str) -> Option<String> {
+    const CONTAINER_ID_LENGTH: usize = 64;
+    const PREFIXES: [&str; 4] = ["docker-", "cri-containerd-", "crio-", "libpod-"];
+
+    contents.lines().find_map(|line| {
+        let segment = line.rsplit('/').next()?;
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+        let segment = PREFIXES
+            .iter()
+            .find_map(|prefix| segment.strip_prefix(prefix))
+            .unwrap_or(segment);
+        (segment.len() == CONTAINER_ID_LENGTH && segment.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| segment.to_owned())
+    })
+}
+
+/// Detects the Kubernetes pod, namespace, and node.
+///
+/// These are read from environment variables, which are expected to be
+/// populated through the Kubernetes downward API, e.g.:
+///
+/// ```yaml
+/// env:
+///   - name: K8S_POD_NAME
+///     valueFrom:
+///       fieldRef:
+///         fieldPath: metadata.name
+///   - name: K8S_POD_UID
+///     valueFrom:
+///       fieldRef:
+///         fieldPath: metadata.uid
+///   - name: K8S_NAMESPACE_NAME
+///     valueFrom:
+///       fieldRef:
+///         fieldPath: metadata.namespace
+///   - name: K8S_NODE_NAME
+///     valueFrom:
+///       fieldRef:
+///         fieldPath: spec.nodeName
+/// ```
+#[derive(Debug)]
+pub struct KubernetesResourceDetector;
+
+impl ResourceDetector for KubernetesResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        Resource::new(
+            [
+                (semcov::resource::K8S_POD_NAME, K8S_POD_NAME_VAR),
+                (semcov::resource::K8S_POD_UID, K8S_POD_UID_VAR),
+                (semcov::resource::K8S_NAMESPACE_NAME, K8S_NAMESPACE_NAME_VAR),
+                (semcov::resource::K8S_NODE_NAME, K8S_NODE_NAME_VAR),
+            ]
+            .into_iter()
+            .filter_map(|(key, var)| env_var(var).map(|value| KeyValue::new(key, value))),
+        )
+    }
+}
+
+/// Detects `deployment.environment` from the `DEPLOYMENT_ENVIRONMENT`
+/// environment variable.
+#[derive(Debug)]
+pub struct DeploymentResourceDetector;
+
+impl ResourceDetector for DeploymentResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        Resource::new(
+            env_var(DEPLOYMENT_ENVIRONMENT_VAR)
+                .map(|value| KeyValue::new(semcov::resource::DEPLOYMENT_ENVIRONMENT, value)),
+        )
+    }
+}
+
+/// Reads an environment variable, ignoring it if it is empty.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_container_id;
+
+    const ID: &str = "2f3a9c1b7d8e4f5a6b0c1d2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a";
+
+    #[test]
+    fn parses_container_ids_from_cgroups() {
+        let cases = [
+            // cgroup v1
+            (format!("12:memory:/docker/{ID}"), Some(ID)),
+            (
+                format!("11:cpu:/kubepods/burstable/pod1b2c3d4e-0000-1111-2222-333344445555/{ID}"),
+                Some(ID),
+            ),
+            (
+                format!("12:pids:/user.slice\n11:memory:/docker/{ID}\n0::/"),
+                Some(ID),
+            ),
+            // cgroup v2
+            (format!("0::/system.slice/docker-{ID}.scope"), Some(ID)),
+            (
+                format!(
+                    "0::/kubepods.slice/kubepods-burstable.slice/\
+                     kubepods-burstable-pod1b2c3d4e.slice/cri-containerd-{ID}.scope"
+                ),
+                Some(ID),
+            ),
+            (format!("0::/machine.slice/libpod-{ID}.scope"), Some(ID)),
+            (format!("0::/kubepods.slice/crio-{ID}.scope"), Some(ID)),
+            // not in a container
+            (String::new(), None),
+            ("0::/".to_owned(), None),
+            (
+                "0::/user.slice/user-1000.slice/session-1.scope".to_owned(),
+                None,
+            ),
+            ("12:memory:/\n11:cpu:/init.scope".to_owned(), None),
+            // not a container ID
+            (format!("0::/system.slice/docker-{}.scope", &ID[1..]), None),
+            (format!("12:memory:/docker/{}", ID.replace('a', "g")), None),
+        ];
+
+        for (contents, expected) in cases {
+            assert_eq!(
+                parse_container_id(&contents).as_deref(),
+                expected,
+                "Unexpected container ID in {contents:?}"
+            );
+        }
+    }
+}
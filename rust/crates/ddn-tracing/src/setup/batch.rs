@@ -0,0 +1,323 @@
+//! Batches spans before export, and reports spans which could not be exported.
+
+use std::env;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use opentelemetry::trace::TraceResult;
+use opentelemetry::Context;
+use opentelemetry_sdk::export::trace::{SpanData, SpanExporter};
+use opentelemetry_sdk::trace::{BatchConfigBuilder, BatchSpanProcessor, Span, SpanProcessor};
+use tracing::Dispatch;
+
+use super::SetupError;
+use crate::export::{ExportQueue, ExportStats, StatsExporter};
+
+const DEFAULT_MAX_QUEUE_SIZE: usize = 2048;
+const DEFAULT_MAX_EXPORT_BATCH_SIZE: usize = 512;
+const DEFAULT_SCHEDULED_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_EXPORT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The batch span processor shares its queue between spans and control
+/// messages, such as requests to flush. We limit the number of spans
+/// ourselves, and leave this much room for control messages.
+const CONTROL_MESSAGE_CAPACITY: usize = 16;
+
+/// Configures how spans are batched before they are exported.
+///
+/// Each exporter gets its own queue and batches. Every setting can also be
+/// configured by the standard environment variables, which are only used for
+/// settings which are not set here:
+///
+///   * https://opentelemetry.io/docs/specs/otel/configuration/sdk-environment-variables/#batch-span-processor
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchSettings {
+    max_queue_size: Option<usize>,
+    max_export_batch_size: Option<usize>,
+    scheduled_delay: Option<Duration>,
+    max_export_timeout: Option<Duration>,
+}
+
+impl BatchSettings {
+    /// Creates settings configured through the environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of spans which can be waiting to be exported, or
+    /// being exported, at once. Spans are dropped when the queue is full.
+    /// This defaults to 2048.
+    #[must_use]
+    pub fn with_max_queue_size(mut self, max_queue_size: usize) -> Self {
+        self.max_queue_size = Some(max_queue_size);
+        self
+    }
+
+    /// Sets the maximum number of spans exported at once. This defaults to
+    /// 512, and is at most the queue size.
+    #[must_use]
+    pub fn with_max_export_batch_size(mut self, max_export_batch_size: usize) -> Self {
+        self.max_export_batch_size = Some(max_export_batch_size);
+        self
+    }
+
+    /// Sets how long to wait for a batch to fill up before exporting it
+    /// anyway. This defaults to 5 seconds.
+    #[must_use]
+    pub fn with_scheduled_delay(mut self, scheduled_delay: Duration) -> Self {
+        self.scheduled_delay = Some(scheduled_delay);
+        self
+    }
+
+    /// Sets how long to wait for a batch to be exported before giving up.
+    /// This defaults to 30 seconds.
+    #[must_use]
+    pub fn with_max_export_timeout(mut self, max_export_timeout: Duration) -> Self {
+        self.max_export_timeout = Some(max_export_timeout);
+        self
+    }
+
+    /// Fills in any settings from the environment, or the defaults.
+    pub(super) fn resolve(self) -> Result<Self, SetupError> {
+        let max_queue_size = or_env_var(self.max_queue_size, "OTEL_BSP_MAX_QUEUE_SIZE")?
+            .unwrap_or(DEFAULT_MAX_QUEUE_SIZE);
+        let max_export_batch_size =
+            or_env_var(self.max_export_batch_size, "OTEL_BSP_MAX_EXPORT_BATCH_SIZE")?
+                .unwrap_or(DEFAULT_MAX_EXPORT_BATCH_SIZE)
+                .min(max_queue_size);
+        let scheduled_delay = or_env_millis(self.scheduled_delay, "OTEL_BSP_SCHEDULE_DELAY")?
+            .unwrap_or(DEFAULT_SCHEDULED_DELAY);
+        let max_export_timeout = or_env_millis(self.max_export_timeout, "OTEL_BSP_EXPORT_TIMEOUT")?
+            .unwrap_or(DEFAULT_MAX_EXPORT_TIMEOUT);
+        Ok(Self {
+            max_queue_size: Some(max_queue_size),
+            max_export_batch_size: Some(max_export_batch_size),
+            scheduled_delay: Some(scheduled_delay),
+            max_export_timeout: Some(max_export_timeout),
+        })
+    }
+
+    /// Builds a batch span processor for the given exporter, counting the
+    /// spans it handles in `stats`, unless the exporter counts those it
+    /// exports itself.
+    ///
+    /// The settings must be resolved first.
+    pub(super) fn processor(
+        self,
+        exporter: Box<dyn SpanExporter>,
+        stats: ExportStats,
+        exporter_counts_spans: bool,
+    ) -> impl SpanProcessor {
+        let max_queue_size = self.max_queue_size.unwrap_or(DEFAULT_MAX_QUEUE_SIZE);
+        let queue = ExportQueue::new(max_queue_size);
+        let exporter = StatsExporter::new(
+            exporter,
+            (!exporter_counts_spans).then(|| stats.clone()),
+            queue.clone(),
+        );
+        let config = BatchConfigBuilder::default()
+            .with_max_queue_size(max_queue_size + CONTROL_MESSAGE_CAPACITY)
+            .with_max_export_batch_size(
+                self.max_export_batch_size
+                    .unwrap_or(DEFAULT_MAX_EXPORT_BATCH_SIZE),
+            )
+            .with_scheduled_delay(self.scheduled_delay.unwrap_or(DEFAULT_SCHEDULED_DELAY))
+            .with_max_export_timeout(
+                self.max_export_timeout
+                    .unwrap_or(DEFAULT_MAX_EXPORT_TIMEOUT),
+            )
+            .build();
+        QueueLimitProcessor {
+            inner: BatchSpanProcessor::builder(exporter, opentelemetry_sdk::runtime::Tokio)
+                .with_batch_config(config)
+                .build(),
+            queue,
+            stats,
+        }
+    }
+}
+
+/// Wraps a batch span processor, dropping spans when its queue is full and
+/// counting them in [`ExportStats`].
+///
+/// The batch span processor drops spans too, but only reports this through
+/// the global OpenTelemetry error handler.
+#[derive(Debug)]
+struct QueueLimitProcessor<P> {
+    inner: P,
+    queue: ExportQueue,
+    stats: ExportStats,
+}
+
+impl<P: SpanProcessor> SpanProcessor for QueueLimitProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // Unsampled spans are never exported, so they do not take up space.
+        if !span.span_context.is_sampled() {
+            return;
+        }
+        if self.queue.try_push() {
+            self.inner.on_end(span);
+        } else {
+            self.stats.record_dropped();
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// Passes each span on to every inner processor, e.g. one for each exporter.
+#[derive(Debug)]
+pub(super) struct FanOutProcessor {
+    inner: Vec<Box<dyn SpanProcessor>>,
+}
+
+impl FanOutProcessor {
+    pub fn new(inner: Vec<Box<dyn SpanProcessor>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl SpanProcessor for FanOutProcessor {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        for processor in &self.inner {
+            processor.on_start(span, cx);
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if let Some((last, rest)) = self.inner.split_last() {
+            for processor in rest {
+                processor.on_end(span.clone());
+            }
+            last.on_end(span);
+        }
+    }
+
+    /// Flushes each processor on its own thread, so that a slow exporter does
+    /// not hold up the others.
+    fn force_flush(&self) -> TraceResult<()> {
+        thread::scope(|scope| {
+            let flushes = self
+                .inner
+                .iter()
+                .map(|processor| scope.spawn(|| processor.force_flush()))
+                .collect::<Vec<_>>();
+            join_all(flushes, "flushing span processor panicked")
+        })
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        thread::scope(|scope| {
+            let shutdowns = self
+                .inner
+                .iter_mut()
+                .map(|processor| scope.spawn(|| processor.shutdown()))
+                .collect::<Vec<_>>();
+            join_all(shutdowns, "shutting down span processor panicked")
+        })
+    }
+}
+
+/// Waits for every thread, returning the first error, if any.
+fn join_all(
+    threads: Vec<thread::ScopedJoinHandle<'_, TraceResult<()>>>,
+    panic_message: &'static str,
+) -> TraceResult<()> {
+    threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap_or_else(|_| Err(panic_message.into())))
+        .fold(Ok(()), Result::and)
+}
+
+/// Logs a warning every `interval` if any spans were dropped or failed to
+/// export in that time.
+///
+/// If a dispatcher is given, the warning is sent there, rather than to the
+/// default subscriber.
+pub(super) fn warn_periodically(
+    stats: ExportStats,
+    interval: Duration,
+    dispatch: Option<Dispatch>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let (mut dropped, mut failed) = (stats.dropped(), stats.failed());
+        loop {
+            ticks.tick().await;
+            let (total_dropped, total_failed) = (stats.dropped(), stats.failed());
+            let (newly_dropped, newly_failed) = (total_dropped - dropped, total_failed - failed);
+            (dropped, failed) = (total_dropped, total_failed);
+            if newly_dropped == 0 && newly_failed == 0 {
+                continue;
+            }
+            let warn = || {
+                tracing::warn!(
+                    spans_dropped = newly_dropped,
+                    spans_failed = newly_failed,
+                    total_spans_dropped = dropped,
+                    total_spans_failed = failed,
+                    "some spans could not be exported",
+                );
+            };
+            match &dispatch {
+                Some(dispatch) => tracing::dispatcher::with_default(dispatch, warn),
+                None => warn(),
+            }
+        }
+    })
+}
+
+/// Parses a numeric environment variable, if it is set.
+fn env_var<T>(name: &'static str) -> Result<Option<T>, SetupError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(source) => Err(SetupError::InvalidEnvVar {
+                name,
+                value,
+                source: Box::new(source),
+            }),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the value if it was set explicitly, and otherwise parses the
+/// environment variable, if it is set.
+pub(super) fn or_env_var<T>(value: Option<T>, name: &'static str) -> Result<Option<T>, SetupError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match value {
+        Some(value) => Ok(Some(value)),
+        None => env_var(name),
+    }
+}
+
+/// Like [`or_env_var`], for durations given in milliseconds.
+fn or_env_millis(
+    value: Option<Duration>,
+    name: &'static str,
+) -> Result<Option<Duration>, SetupError> {
+    match value {
+        Some(value) => Ok(Some(value)),
+        None => Ok(env_var(name)?.map(Duration::from_millis)),
+    }
+}
@@ -0,0 +1,73 @@
+//! Errors that can occur when setting up tracing.
+
+use std::error::Error;
+
+use opentelemetry::trace::TraceError;
+use tracing_subscriber::util::TryInitError;
+
+/// The reasons tracing setup can fail.
+///
+/// Services may choose to continue without telemetry on some of these, and
+/// fail on others. Setup does not install any global state unless it
+/// succeeds.
+#[derive(Debug, derive_more::Display)]
+pub enum SetupError {
+    /// The endpoint passed explicitly is not a valid URI.
+    #[display(fmt = "invalid OTLP endpoint {endpoint:?}")]
+    InvalidEndpoint {
+        endpoint: String,
+        source: http::uri::InvalidUri,
+    },
+    /// An environment variable used to configure tracing has an invalid value.
+    #[display(fmt = "invalid value for the environment variable {name}: {value:?}")]
+    InvalidEnvVar {
+        name: &'static str,
+        value: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A header passed explicitly is not a valid gRPC metadata entry.
+    #[display(fmt = "invalid OTLP header {name:?}")]
+    InvalidHeader {
+        name: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A pattern passed to [`super::Scrubber::with_pattern`] is not a valid
+    /// regular expression.
+    #[display(fmt = "invalid scrubbing pattern {pattern:?}")]
+    InvalidScrubPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// The TLS certificates or key could not be loaded.
+    #[display(fmt = "invalid TLS configuration for the OTLP exporter")]
+    InvalidTlsConfig { source: tonic::transport::Error },
+    /// Tracing must be set up from within a Tokio runtime, as spans are
+    /// exported in a background task.
+    #[display(fmt = "tracing must be set up from within a Tokio runtime")]
+    NoTokioRuntime {
+        source: tokio::runtime::TryCurrentError,
+    },
+    /// A global `tracing` subscriber has already been set, either by a
+    /// previous setup or by another library.
+    #[display(fmt = "a global tracing subscriber has already been set")]
+    SubscriberAlreadySet { source: TryInitError },
+    /// The span exporter could not be built.
+    #[display(fmt = "failed to build the span exporter")]
+    Exporter { source: TraceError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidEndpoint { source, .. } => Some(source),
+            Self::InvalidEnvVar { source, .. } | Self::InvalidHeader { source, .. } => {
+                Some(source.as_ref())
+            }
+            Self::InvalidScrubPattern { source, .. } => Some(source),
+            Self::InvalidTlsConfig { source } => Some(source),
+            Self::NoTokioRuntime { source } => Some(source),
+            Self::SubscriberAlreadySet { source } => Some(source),
+            Self::Exporter { source } => Some(source),
+        }
+    }
+}
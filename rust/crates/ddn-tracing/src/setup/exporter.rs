@@ -0,0 +1,193 @@
+//! The destinations spans can be sent to.
+
+use std::env;
+
+use opentelemetry_sdk::export::trace::SpanExporter;
+
+use super::{OtlpExporter, SetupError};
+use crate::export::{
+    ConsoleExporter, ConsoleFormat, ExportStats, FileExporter, RedactingExporter, Redaction,
+    RetryExporter, RetryQueue,
+};
+
+/// A destination for spans.
+///
+/// Each exporter gets its own batch span processor, so a slow or failing
+/// exporter does not hold up the others.
+#[derive(Debug)]
+pub enum Exporter {
+    /// Sends spans to a collector using OTLP over gRPC.
+    Otlp(OtlpExporter),
+    /// Prints spans to standard error, for local development.
+    Console(ConsoleExporter),
+    /// Appends spans to local files, in the OTLP JSON encoding.
+    File(FileExporter),
+    /// Sends spans to any other exporter.
+    Custom(Box<dyn SpanExporter>),
+    /// Sends spans to another exporter, retrying those it could not send
+    /// from a queue on disk. See [`RetryExporter`].
+    Retry(Box<Exporter>, RetryQueue),
+    /// Sends spans to another exporter, after redacting their attributes.
+    /// See [`RedactingExporter`].
+    Redacted(Box<Exporter>, Redaction),
+    /// Sends only the spans users should see to another exporter. See
+    /// [`Exporter::with_user_visible_spans_only`].
+    UserVisible(Box<Exporter>),
+}
+
+impl Exporter {
+    /// Wraps any other exporter.
+    pub fn custom(exporter: impl SpanExporter + 'static) -> Self {
+        Self::Custom(Box::new(exporter))
+    }
+
+    /// Retries spans which this exporter could not send, keeping them in the
+    /// given queue on disk in the meantime.
+    #[must_use]
+    pub fn with_retry_queue(self, queue: RetryQueue) -> Self {
+        Self::Retry(Box::new(self), queue)
+    }
+
+    /// Redacts attributes before they reach this exporter.
+    ///
+    /// Add this before a retry queue, so that redacted values are never
+    /// written to disk.
+    #[must_use]
+    pub fn with_redaction(self, redaction: Redaction) -> Self {
+        Self::Redacted(Box::new(self), redaction)
+    }
+
+    /// Sends only spans with `user` visibility to this exporter, for
+    /// customer-facing trace views.
+    ///
+    /// Children of internal spans are re-parented onto their nearest
+    /// user-visible ancestor. To do so, spans are held back until the local
+    /// root span of their trace ends.
+    #[must_use]
+    pub fn with_user_visible_spans_only(self) -> Self {
+        Self::UserVisible(Box::new(self))
+    }
+
+    /// Whether only user-visible spans should reach this exporter.
+    pub(super) fn is_user_visible_only(&self) -> bool {
+        match self {
+            Self::UserVisible(_) => true,
+            Self::Retry(exporter, _) | Self::Redacted(exporter, _) => {
+                exporter.is_user_visible_only()
+            }
+            Self::Otlp(_) | Self::Console(_) | Self::File(_) | Self::Custom(_) => false,
+        }
+    }
+
+    /// Whether the built exporter counts the spans it exports in the stats
+    /// passed to [`Exporter::build`], as spilled spans are only exported or
+    /// failed once they have been retried.
+    pub(super) fn counts_spans(&self) -> bool {
+        match self {
+            Self::Retry(_, _) => true,
+            Self::Redacted(exporter, _) | Self::UserVisible(exporter) => exporter.counts_spans(),
+            Self::Otlp(_) | Self::Console(_) | Self::File(_) | Self::Custom(_) => false,
+        }
+    }
+
+    /// Builds the underlying span exporter.
+    pub(super) fn build(self, stats: &ExportStats) -> Result<Box<dyn SpanExporter>, SetupError> {
+        match self {
+            Self::Otlp(otlp) => otlp.build(),
+            Self::Console(console) => Ok(Box::new(console)),
+            Self::File(file) => Ok(Box::new(file)),
+            Self::Custom(exporter) => Ok(exporter),
+            Self::Retry(exporter, queue) => Ok(Box::new(RetryExporter::from_boxed(
+                exporter.build(stats)?,
+                queue,
+                stats.clone(),
+            ))),
+            Self::Redacted(exporter, redaction) => Ok(Box::new(RedactingExporter::from_boxed(
+                exporter.build(stats)?,
+                redaction,
+            ))),
+            // Spans are filtered by the span processor, as only it can tell
+            // which spans are local roots.
+            Self::UserVisible(exporter) => exporter.build(stats),
+        }
+    }
+}
+
+impl From<OtlpExporter> for Exporter {
+    fn from(otlp: OtlpExporter) -> Self {
+        Self::Otlp(otlp)
+    }
+}
+
+impl From<ConsoleExporter> for Exporter {
+    fn from(console: ConsoleExporter) -> Self {
+        Self::Console(console)
+    }
+}
+
+impl From<FileExporter> for Exporter {
+    fn from(file: FileExporter) -> Self {
+        Self::File(file)
+    }
+}
+
+/// Chooses the exporters to use when none are configured explicitly, from
+/// `OTEL_TRACES_EXPORTER`.
+///
+/// This is a comma-separated list of `otlp` (the default), `console`, or
+/// `none`. The console format can be chosen with
+/// `DDN_TRACING_CONSOLE_FORMAT`, which is either `tree` (the default) or
+/// `otlp_json`.
+pub(super) fn from_env(endpoint: Option<String>) -> Result<Vec<Exporter>, SetupError> {
+    const NAME: &str = "OTEL_TRACES_EXPORTER";
+    let Ok(value) = env::var(NAME) else {
+        return Ok(vec![default_otlp(endpoint)]);
+    };
+
+    let mut exporters = Vec::new();
+    let mut endpoint = Some(endpoint);
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match name {
+            "otlp" => {
+                if let Some(endpoint) = endpoint.take() {
+                    exporters.push(default_otlp(endpoint));
+                }
+            }
+            "console" => exporters.push(ConsoleExporter::new(console_format_from_env()?).into()),
+            "none" => {}
+            _ => {
+                return Err(SetupError::InvalidEnvVar {
+                    name: NAME,
+                    value: value.clone(),
+                    source: format!("unknown exporter {name:?}").into(),
+                })
+            }
+        }
+    }
+    Ok(exporters)
+}
+
+fn default_otlp(endpoint: Option<String>) -> Exporter {
+    let mut otlp = OtlpExporter::new();
+    if let Some(endpoint) = endpoint {
+        otlp = otlp.with_endpoint(endpoint);
+    }
+    otlp.into()
+}
+
+fn console_format_from_env() -> Result<ConsoleFormat, SetupError> {
+    const NAME: &str = "DDN_TRACING_CONSOLE_FORMAT";
+    match env::var(NAME).as_deref() {
+        Err(_) | Ok("tree") => Ok(ConsoleFormat::Tree),
+        Ok("otlp_json") => Ok(ConsoleFormat::OtlpJson),
+        Ok(value) => Err(SetupError::InvalidEnvVar {
+            name: NAME,
+            value: value.to_owned(),
+            source: "expected \"tree\" or \"otlp_json\"".into(),
+        }),
+    }
+}
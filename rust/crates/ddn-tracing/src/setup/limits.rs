@@ -0,0 +1,242 @@
+//! Limits the size of spans, so that collectors do not reject them.
+
+use opentelemetry::trace::TraceResult;
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{Span, SpanProcessor};
+
+use super::batch::or_env_var;
+use super::SetupError;
+
+/// The default for every count limit, as per the specification.
+const DEFAULT_COUNT_LIMIT: u32 = 128;
+
+/// The default length, in bytes, beyond which string attribute values are
+/// truncated. The specification does not limit them by default, but values
+/// such as error details and backtraces can be large enough for collectors
+/// to reject whole batches.
+const DEFAULT_MAX_ATTRIBUTE_VALUE_LENGTH: usize = 16 * 1024;
+
+/// Ends attribute values which were truncated.
+pub const TRUNCATION_MARKER: &str = "...[truncated]";
+
+/// Configures how much can be recorded on each span.
+///
+/// Attributes, events and links beyond the limits are dropped, and the
+/// number dropped is exported with the span. String attribute values beyond
+/// the maximum length are truncated to that length, and end with
+/// [`TRUNCATION_MARKER`].
+///
+/// Every setting can also be configured by the standard environment
+/// variables, which are only used for settings which are not set here:
+///
+///   * https://opentelemetry.io/docs/specs/otel/configuration/sdk-environment-variables/#span-limits
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpanLimits {
+    max_attributes_per_span: Option<u32>,
+    max_events_per_span: Option<u32>,
+    max_links_per_span: Option<u32>,
+    max_attributes_per_event: Option<u32>,
+    max_attributes_per_link: Option<u32>,
+    max_attribute_value_length: Option<usize>,
+}
+
+impl SpanLimits {
+    /// Creates limits configured through the environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of attributes kept on each span. This defaults to 128.
+    #[must_use]
+    pub fn with_max_attributes_per_span(mut self, max_attributes_per_span: u32) -> Self {
+        self.max_attributes_per_span = Some(max_attributes_per_span);
+        self
+    }
+
+    /// Sets the number of events kept on each span. This defaults to 128.
+    #[must_use]
+    pub fn with_max_events_per_span(mut self, max_events_per_span: u32) -> Self {
+        self.max_events_per_span = Some(max_events_per_span);
+        self
+    }
+
+    /// Sets the number of links kept on each span. This defaults to 128.
+    #[must_use]
+    pub fn with_max_links_per_span(mut self, max_links_per_span: u32) -> Self {
+        self.max_links_per_span = Some(max_links_per_span);
+        self
+    }
+
+    /// Sets the number of attributes kept on each event. This defaults to
+    /// 128.
+    #[must_use]
+    pub fn with_max_attributes_per_event(mut self, max_attributes_per_event: u32) -> Self {
+        self.max_attributes_per_event = Some(max_attributes_per_event);
+        self
+    }
+
+    /// Sets the number of attributes kept on each link. This defaults to
+    /// 128.
+    #[must_use]
+    pub fn with_max_attributes_per_link(mut self, max_attributes_per_link: u32) -> Self {
+        self.max_attributes_per_link = Some(max_attributes_per_link);
+        self
+    }
+
+    /// Sets the length, in bytes, beyond which string attribute values are
+    /// truncated. This defaults to 16 KiB.
+    ///
+    /// The marker counts towards the length, unless the length is too short
+    /// to fit it, in which case values are truncated without it.
+    #[must_use]
+    pub fn with_max_attribute_value_length(mut self, max_attribute_value_length: usize) -> Self {
+        self.max_attribute_value_length = Some(max_attribute_value_length);
+        self
+    }
+
+    /// Fills in any settings which are not set from the environment, or the
+    /// defaults.
+    pub(super) fn resolve(self) -> Result<Self, SetupError> {
+        let count = |value: Option<u32>, names: &[&'static str]| -> Result<_, SetupError> {
+            Ok(Some(
+                or_env_vars(value, names)?.unwrap_or(DEFAULT_COUNT_LIMIT),
+            ))
+        };
+        Ok(Self {
+            max_attributes_per_span: count(
+                self.max_attributes_per_span,
+                &[
+                    "OTEL_SPAN_ATTRIBUTE_COUNT_LIMIT",
+                    "OTEL_ATTRIBUTE_COUNT_LIMIT",
+                ],
+            )?,
+            max_events_per_span: count(self.max_events_per_span, &["OTEL_SPAN_EVENT_COUNT_LIMIT"])?,
+            max_links_per_span: count(self.max_links_per_span, &["OTEL_SPAN_LINK_COUNT_LIMIT"])?,
+            max_attributes_per_event: count(
+                self.max_attributes_per_event,
+                &["OTEL_EVENT_ATTRIBUTE_COUNT_LIMIT"],
+            )?,
+            max_attributes_per_link: count(
+                self.max_attributes_per_link,
+                &["OTEL_LINK_ATTRIBUTE_COUNT_LIMIT"],
+            )?,
+            max_attribute_value_length: Some(
+                or_env_vars(
+                    self.max_attribute_value_length,
+                    &[
+                        "OTEL_SPAN_ATTRIBUTE_VALUE_LENGTH_LIMIT",
+                        "OTEL_ATTRIBUTE_VALUE_LENGTH_LIMIT",
+                    ],
+                )?
+                .unwrap_or(DEFAULT_MAX_ATTRIBUTE_VALUE_LENGTH),
+            ),
+        })
+    }
+
+    /// The count limits, which are applied by the tracer provider.
+    ///
+    /// The limits must be resolved first.
+    pub(super) fn sdk_limits(self) -> opentelemetry_sdk::trace::SpanLimits {
+        let limit = |value: Option<u32>| value.unwrap_or(DEFAULT_COUNT_LIMIT);
+        opentelemetry_sdk::trace::SpanLimits {
+            max_events_per_span: limit(self.max_events_per_span),
+            max_attributes_per_span: limit(self.max_attributes_per_span),
+            max_links_per_span: limit(self.max_links_per_span),
+            max_attributes_per_event: limit(self.max_attributes_per_event),
+            max_attributes_per_link: limit(self.max_attributes_per_link),
+        }
+    }
+
+    /// Wraps a span processor, truncating long attribute values before they
+    /// reach it.
+    ///
+    /// The limits must be resolved first.
+    pub(super) fn processor<P: SpanProcessor>(self, inner: P) -> TruncatingProcessor<P> {
+        TruncatingProcessor {
+            inner,
+            max_length: self
+                .max_attribute_value_length
+                .unwrap_or(DEFAULT_MAX_ATTRIBUTE_VALUE_LENGTH),
+        }
+    }
+}
+
+/// Truncates string attribute values on spans, events and links, marking
+/// them with [`TRUNCATION_MARKER`].
+///
+/// This comes before spans are passed on to each exporter, so that each span
+/// is only truncated once.
+#[derive(Debug)]
+pub(super) struct TruncatingProcessor<P> {
+    inner: P,
+    max_length: usize,
+}
+
+impl<P: SpanProcessor> SpanProcessor for TruncatingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        truncate_attributes(&mut span.attributes, self.max_length);
+        for event in &mut span.events.events {
+            truncate_attributes(&mut event.attributes, self.max_length);
+        }
+        for link in &mut span.links.links {
+            truncate_attributes(&mut link.attributes, self.max_length);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+fn truncate_attributes(attributes: &mut [KeyValue], max_length: usize) {
+    for attribute in attributes {
+        match &mut attribute.value {
+            Value::String(value) => truncate(value, max_length),
+            Value::Array(Array::String(values)) => {
+                for value in values {
+                    truncate(value, max_length);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Truncates a value to at most `max_length` bytes, including the marker if
+/// it fits, cutting on a character boundary.
+fn truncate(value: &mut StringValue, max_length: usize) {
+    let string = value.as_str();
+    if string.len() <= max_length {
+        return;
+    }
+    let (mut end, marker) = match max_length.checked_sub(TRUNCATION_MARKER.len()) {
+        Some(end) => (end, TRUNCATION_MARKER),
+        None => (max_length, ""),
+    };
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+    *value = format!("{}{marker}", &string[..end]).into();
+}
+
+/// Returns the value if it was set explicitly, and otherwise parses the first
+/// of the environment variables which is set.
+fn or_env_vars<T>(value: Option<T>, names: &[&'static str]) -> Result<Option<T>, SetupError>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    names
+        .iter()
+        .try_fold(value, |value, &name| or_env_var(value, name))
+}
@@ -0,0 +1,597 @@
+//! Sets up tracing, either globally or scoped to a particular piece of code.
+
+mod batch;
+mod error;
+mod exporter;
+mod limits;
+mod otlp;
+mod scrub;
+mod shutdown;
+mod user_visible;
+
+use std::borrow::Cow;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::metrics::Meter;
+use opentelemetry::propagation::composite::TextMapCompositePropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SpanProcessor, TracerProvider};
+use opentelemetry_semantic_conventions as semcov;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::export::ExportStats;
+use shutdown::{LogWriter, Pipeline, Providers};
+
+pub use batch::BatchSettings;
+pub use error::SetupError;
+pub use exporter::Exporter;
+pub use limits::{SpanLimits, TRUNCATION_MARKER};
+pub use otlp::{Compression, OtlpExporter};
+pub use scrub::Scrubber;
+pub use shutdown::ShutdownReport;
+
+const DEFAULT_LEVEL: tracing::level_filters::LevelFilter =
+    tracing::level_filters::LevelFilter::INFO;
+
+/// How often to warn about spans which could not be exported, by default.
+const DEFAULT_EXPORT_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The environment variable which chooses the log format, if it is not set
+/// with [`Builder::with_log_format`].
+const LOG_FORMAT_ENV_VAR: &str = "DDN_TRACING_LOG_FORMAT";
+
+/// How log lines are written to standard output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One JSON object per line, for log aggregation.
+    #[default]
+    Json,
+    /// Human-readable, multi-line output, for local development.
+    Pretty,
+}
+
+impl LogFormat {
+    /// Reads the format from `DDN_TRACING_LOG_FORMAT`, which is either
+    /// `json` (the default) or `pretty`.
+    fn from_env() -> Result<Self, SetupError> {
+        match env::var(LOG_FORMAT_ENV_VAR).as_deref() {
+            Err(_) | Ok("json") => Ok(Self::Json),
+            Ok("pretty") => Ok(Self::Pretty),
+            Ok(value) => Err(SetupError::InvalidEnvVar {
+                name: LOG_FORMAT_ENV_VAR,
+                value: value.to_owned(),
+                source: "expected \"json\" or \"pretty\"".into(),
+            }),
+        }
+    }
+}
+
+/// The installed global tracing setup.
+///
+/// Call [`GlobalTracing::shutdown`] before exiting to make sure all spans are
+/// exported. If this is dropped without being shut down, it will wait a
+/// bounded amount of time for pending spans instead, unless it is dropped
+/// inside a current-thread runtime, where waiting would block the exports.
+pub struct GlobalTracing {
+    pipeline: Pipeline,
+}
+
+impl GlobalTracing {
+    /// Unregisters the global tracing provider, exports any pending spans,
+    /// and flushes metrics and logs, waiting at most `timeout` for them to
+    /// be sent.
+    ///
+    /// The report describes whether any spans were lost.
+    pub async fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+        global::shutdown_tracer_provider();
+        self.pipeline.shutdown(timeout).await
+    }
+}
+
+impl Drop for GlobalTracing {
+    fn drop(&mut self) {
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// A tracing setup which has not been installed globally.
+///
+/// Several of these can coexist in one process. To use one, install its
+/// subscriber for a particular scope, e.g. with
+/// [`tracing::subscriber::with_default`] or
+/// [`tracing::subscriber::set_default`].
+///
+/// The text map propagator is not installed, as OpenTelemetry only supports a
+/// global propagator.
+///
+/// # Example:
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let scoped = ddn_tracing::setup::Builder::new("test", "1.0.0")
+///     .with_endpoint("http://localhost:4317")
+///     .build()?;
+/// tracing::subscriber::with_default(scoped.subscriber(), || {
+///     tracing::info_span!("scoped").in_scope(|| tracing::info!("hello"));
+/// });
+/// let report = scoped.shutdown(std::time::Duration::from_secs(5)).await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScopedTracing {
+    subscriber: Arc<dyn Subscriber + Send + Sync>,
+    pipeline: Pipeline,
+}
+
+impl ScopedTracing {
+    /// The subscriber, which records spans and sends them to the tracer
+    /// provider.
+    ///
+    /// Clones of this share the same underlying subscriber.
+    pub fn subscriber(&self) -> Arc<dyn Subscriber + Send + Sync> {
+        self.subscriber.clone()
+    }
+
+    /// The tracer provider, which exports spans. This can be used to create
+    /// OpenTelemetry tracers directly.
+    ///
+    /// This is only `None` after shutdown.
+    pub fn tracer_provider(&self) -> Option<&TracerProvider> {
+        self.pipeline.tracer_provider()
+    }
+
+    /// Exports any pending spans, and flushes metrics and logs, waiting at
+    /// most `timeout` for them to be sent.
+    ///
+    /// The report describes whether any spans were lost.
+    pub async fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+        self.pipeline.shutdown(timeout).await
+    }
+}
+
+/// Initialize a generic tracing setup that exports traces, and install it as
+/// the global tracing provider.
+///
+/// This is a shorthand for [`Builder`], which provides more options.
+///
+/// The tracing provider will be unregistered on drop.
+///
+/// Most configuration is done by standard environment variables:
+///
+///   * https://opentelemetry.io/docs/specs/otel/configuration/sdk-environment-variables/
+///   * https://opentelemetry.io/docs/languages/sdk-configuration/otlp-exporter/
+///
+/// The endpoint can be overridden here by passing a value for the endpoint.
+///
+/// The service name and version get special treatment as we consider them
+/// mandatory. Other resource attributes are detected automatically; see
+/// [`crate::resource`].
+pub fn init_tracing(
+    endpoint: Option<&str>,
+    service_name: impl Into<Cow<'static, str>>,
+    service_version: impl Into<Cow<'static, str>>,
+) -> Result<GlobalTracing, SetupError> {
+    let mut builder = Builder::new(service_name, service_version);
+    if let Some(endpoint) = endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    builder.init()
+}
+
+/// Configures a tracing setup, which can then be installed globally with
+/// [`Builder::init`], or built without installing it with [`Builder::build`].
+///
+/// # Example:
+/// ```no_run
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use ddn_tracing::setup::OtlpExporter;
+///
+/// let tenant = std::env::var("TENANT")?;
+/// let _tracing = ddn_tracing::setup::Builder::new(format!("engine-{tenant}"), "1.2.3")
+///     .with_service_namespace("ddn")
+///     .with_exporter(OtlpExporter::new().with_endpoint("http://collector:4317"))
+///     .with_exporter(OtlpExporter::new().with_endpoint("http://other-collector:4317"))
+///     .init()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    endpoint: Option<String>,
+    exporters: Vec<Exporter>,
+    service_name: Cow<'static, str>,
+    service_version: Cow<'static, str>,
+    service_namespace: Option<Cow<'static, str>>,
+    service_instance_id: Option<Cow<'static, str>>,
+    log_format: Option<LogFormat>,
+    log_writer: Option<BoxMakeWriter>,
+    batch_settings: BatchSettings,
+    span_limits: SpanLimits,
+    meter: Option<Meter>,
+    meter_provider: Option<SdkMeterProvider>,
+    export_warning_interval: Duration,
+    scrubber: Option<Scrubber>,
+    strict_env_filter: bool,
+}
+
+impl Builder {
+    /// Creates a new builder for the given service.
+    ///
+    /// The service name and version are mandatory, and can be chosen at
+    /// runtime.
+    pub fn new(
+        service_name: impl Into<Cow<'static, str>>,
+        service_version: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            endpoint: None,
+            exporters: Vec::new(),
+            service_name: service_name.into(),
+            service_version: service_version.into(),
+            service_namespace: None,
+            service_instance_id: None,
+            log_format: None,
+            log_writer: None,
+            batch_settings: BatchSettings::default(),
+            span_limits: SpanLimits::default(),
+            meter: None,
+            meter_provider: None,
+            export_warning_interval: DEFAULT_EXPORT_WARNING_INTERVAL,
+            scrubber: None,
+            strict_env_filter: false,
+        }
+    }
+
+    /// Overrides the endpoint of the default OTLP exporter, which is
+    /// otherwise configured through `OTEL_EXPORTER_OTLP_ENDPOINT`.
+    ///
+    /// The default exporter is only used when no exporters are added with
+    /// [`Builder::with_exporter`].
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Adds an exporter. Spans are sent to every exporter added.
+    ///
+    /// If none are added, the exporters are chosen by `OTEL_TRACES_EXPORTER`,
+    /// which is a comma-separated list of `otlp` (the default), `console`,
+    /// or `none`.
+    #[must_use]
+    pub fn with_exporter(mut self, exporter: impl Into<Exporter>) -> Self {
+        self.exporters.push(exporter.into());
+        self
+    }
+
+    /// Adds several exporters at once.
+    #[must_use]
+    pub fn with_exporters(mut self, exporters: impl IntoIterator<Item = Exporter>) -> Self {
+        self.exporters.extend(exporters);
+        self
+    }
+
+    /// Sets `service.namespace`, which groups related services together.
+    #[must_use]
+    pub fn with_service_namespace(mut self, namespace: impl Into<Cow<'static, str>>) -> Self {
+        self.service_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Sets `service.instance.id`.
+    ///
+    /// By default, this is a random UUID generated once per process.
+    #[must_use]
+    pub fn with_service_instance_id(mut self, instance_id: impl Into<Cow<'static, str>>) -> Self {
+        self.service_instance_id = Some(instance_id.into());
+        self
+    }
+
+    /// Sets the format of log lines.
+    ///
+    /// Otherwise, this is chosen by `DDN_TRACING_LOG_FORMAT`, which is either
+    /// `json` (the default) or `pretty`.
+    #[must_use]
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = Some(log_format);
+        self
+    }
+
+    /// Sets where log lines are written. This defaults to standard output.
+    #[must_use]
+    pub fn with_log_writer<W>(mut self, log_writer: W) -> Self
+    where
+        W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+    {
+        self.log_writer = Some(BoxMakeWriter::new(log_writer));
+        self
+    }
+
+    /// Configures how spans are batched before they are exported.
+    #[must_use]
+    pub fn with_batch_settings(mut self, batch_settings: BatchSettings) -> Self {
+        self.batch_settings = batch_settings;
+        self
+    }
+
+    /// Limits the number of attributes, events and links on each span, and
+    /// the length of attribute values.
+    #[must_use]
+    pub fn with_span_limits(mut self, span_limits: SpanLimits) -> Self {
+        self.span_limits = span_limits;
+        self
+    }
+
+    /// Sets the meter used to publish the number of spans exported, failed
+    /// and dropped. See [`ExportStats`] for the metric names.
+    ///
+    /// By default, this uses the global meter provider as it is at setup
+    /// time.
+    #[must_use]
+    pub fn with_meter(mut self, meter: Meter) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Publishes the span counts using a meter from the given provider, and
+    /// flushes the provider on shutdown, after the final counts are known.
+    ///
+    /// The provider is not shut down, as it may still be used elsewhere.
+    #[must_use]
+    pub fn with_meter_provider(mut self, meter_provider: SdkMeterProvider) -> Self {
+        use opentelemetry::metrics::MeterProvider as _;
+
+        self.meter = Some(meter_provider.meter(env!("CARGO_PKG_NAME")));
+        self.meter_provider = Some(meter_provider);
+        self
+    }
+
+    /// Sets how often to log a warning if any spans were dropped or failed
+    /// to export. This defaults to once a minute.
+    #[must_use]
+    pub fn with_export_warning_interval(mut self, interval: Duration) -> Self {
+        self.export_warning_interval = interval;
+        self
+    }
+
+    /// Scrubs personal data and secrets from span and event attributes
+    /// before they reach any exporter.
+    #[must_use]
+    pub fn with_scrubber(mut self, scrubber: Scrubber) -> Self {
+        self.scrubber = Some(scrubber);
+        self
+    }
+
+    /// Fails setup if `RUST_LOG` contains an invalid directive. By default,
+    /// invalid directives are skipped.
+    #[must_use]
+    pub fn with_strict_env_filter(mut self) -> Self {
+        self.strict_env_filter = true;
+        self
+    }
+
+    /// Builds the tracing setup, and installs it as the global tracing
+    /// provider.
+    ///
+    /// The tracing provider will be unregistered on drop.
+    pub fn init(self) -> Result<GlobalTracing, SetupError> {
+        let export_warning_interval = self.export_warning_interval;
+        let (subscriber, providers, stats) = self.build_parts()?;
+
+        subscriber
+            .try_init()
+            .map_err(|source| SetupError::SubscriberAlreadySet { source })?;
+
+        global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+            Box::new(TraceContextPropagator::new()),
+            Box::new(opentelemetry_zipkin::Propagator::new()),
+        ]));
+        global::set_tracer_provider(providers.tracer_provider.clone());
+
+        // Warnings go to the global subscriber we just installed.
+        let warnings = batch::warn_periodically(stats.clone(), export_warning_interval, None);
+        Ok(GlobalTracing {
+            pipeline: Pipeline::new(providers, stats, warnings),
+        })
+    }
+
+    /// Builds the tracing setup without installing any global state.
+    ///
+    /// This still needs to be called from within a Tokio runtime, as spans are
+    /// exported in a background task.
+    pub fn build(self) -> Result<ScopedTracing, SetupError> {
+        let export_warning_interval = self.export_warning_interval;
+        let (subscriber, providers, stats) = self.build_parts()?;
+        let subscriber: Arc<dyn Subscriber + Send + Sync> = Arc::new(subscriber);
+        let warnings = batch::warn_periodically(
+            stats.clone(),
+            export_warning_interval,
+            Some(tracing::Dispatch::new(subscriber.clone())),
+        );
+        Ok(ScopedTracing {
+            subscriber,
+            pipeline: Pipeline::new(providers, stats, warnings),
+        })
+    }
+
+    /// Builds the subscriber and the providers it sends telemetry to.
+    fn build_parts(
+        mut self,
+    ) -> Result<
+        (
+            impl Subscriber + Send + Sync + 'static,
+            Providers,
+            ExportStats,
+        ),
+        SetupError,
+    > {
+        tokio::runtime::Handle::try_current()
+            .map_err(|source| SetupError::NoTokioRuntime { source })?;
+
+        let (otel_filter, log_filter) = filters(self.strict_env_filter)?;
+        let log_format = match self.log_format {
+            Some(log_format) => log_format,
+            None => LogFormat::from_env()?,
+        };
+        let log_writer = LogWriter::new(
+            self.log_writer
+                .take()
+                .unwrap_or_else(|| BoxMakeWriter::new(std::io::stdout)),
+        );
+
+        let mut exporters = std::mem::take(&mut self.exporters);
+        if exporters.is_empty() {
+            exporters = exporter::from_env(self.endpoint.take())?;
+        }
+
+        // Each exporter gets its own batch span processor, and therefore its
+        // own queue and background task.
+        let batch_settings = self.batch_settings.resolve()?;
+        let span_limits = self.span_limits.resolve()?;
+        let meter = self
+            .meter
+            .take()
+            .unwrap_or_else(|| global::meter(env!("CARGO_PKG_NAME")));
+        let stats = ExportStats::with_meter(&meter);
+        let meter_provider = self.meter_provider.take();
+        let mut processors: Vec<Box<dyn SpanProcessor>> = Vec::new();
+        for exporter in exporters {
+            let user_visible_only = exporter.is_user_visible_only();
+            let counts_spans = exporter.counts_spans();
+            let processor =
+                batch_settings.processor(exporter.build(&stats)?, stats.clone(), counts_spans);
+            if user_visible_only {
+                processors.push(Box::new(user_visible::UserVisibleProcessor::new(processor)));
+            } else {
+                processors.push(Box::new(processor));
+            }
+        }
+
+        // Spans are scrubbed and then truncated before they are passed on to
+        // each exporter, so that this only happens once for each span, and
+        // values are scrubbed before they can be cut short.
+        let processor = span_limits.processor(batch::FanOutProcessor::new(processors));
+        let mut tracer_provider_builder = TracerProvider::builder();
+        if let Some(scrubber) = self.scrubber.take() {
+            tracer_provider_builder =
+                tracer_provider_builder.with_span_processor(scrubber.processor(&meter, processor)?);
+        } else {
+            tracer_provider_builder = tracer_provider_builder.with_span_processor(processor);
+        }
+        let tracer_provider = tracer_provider_builder
+            .with_config(
+                opentelemetry_sdk::trace::config()
+                    .with_span_limits(span_limits.sdk_limits())
+                    .with_resource(crate::resource::build(self.service_attributes())),
+            )
+            .build();
+        let tracer = tracer_provider.versioned_tracer(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            None::<&'static str>,
+            None,
+        );
+
+        // Only one of these is set.
+        let (json_layer, pretty_layer) = match log_format {
+            LogFormat::Json => (
+                Some(
+                    tracing_subscriber::fmt::layer()
+                        .json()
+                        .with_timer(tracing_subscriber::fmt::time::time())
+                        .with_writer(log_writer.clone())
+                        .with_filter(log_filter),
+                ),
+                None,
+            ),
+            LogFormat::Pretty => (
+                None,
+                Some(
+                    tracing_subscriber::fmt::layer()
+                        .pretty()
+                        .with_writer(log_writer.clone())
+                        .with_filter(log_filter),
+                ),
+            ),
+        };
+
+        let subscriber = tracing_subscriber::registry()
+            .with(
+                tracing_opentelemetry::layer()
+                    .with_error_records_to_exceptions(true)
+                    .with_tracer(tracer)
+                    .with_filter(otel_filter),
+            )
+            .with(json_layer)
+            .with(pretty_layer);
+
+        let providers = Providers {
+            tracer_provider,
+            meter_provider,
+            log_writer,
+        };
+        Ok((subscriber, providers, stats))
+    }
+
+    /// The explicitly-configured service attributes.
+    fn service_attributes(self) -> Vec<KeyValue> {
+        let mut attributes = vec![
+            KeyValue::new(semcov::resource::SERVICE_NAME, self.service_name),
+            KeyValue::new(semcov::resource::SERVICE_VERSION, self.service_version),
+        ];
+        if let Some(namespace) = self.service_namespace {
+            attributes.push(KeyValue::new(
+                semcov::resource::SERVICE_NAMESPACE,
+                namespace,
+            ));
+        }
+        if let Some(instance_id) = self.service_instance_id {
+            attributes.push(KeyValue::new(
+                semcov::resource::SERVICE_INSTANCE_ID,
+                instance_id,
+            ));
+        }
+        attributes
+    }
+}
+
+/// Builds a filter from `RUST_LOG`, defaulting to the `info` level. Invalid
+/// directives are skipped, unless `strict` is set.
+fn env_filter(strict: bool) -> Result<EnvFilter, SetupError> {
+    let builder = EnvFilter::builder().with_default_directive(DEFAULT_LEVEL.into());
+    if !strict {
+        return Ok(builder.from_env_lossy());
+    }
+    builder.from_env().map_err(|source| {
+        let name = EnvFilter::DEFAULT_ENV;
+        SetupError::InvalidEnvVar {
+            name,
+            value: env::var(name).unwrap_or_default(),
+            source: source.into(),
+        }
+    })
+}
+
+/// Builds the filters for exported spans and for logs respectively.
+///
+/// Both follow `RUST_LOG`, except that spans from the old tracer are always
+/// exported and never logged.
+fn filters(strict: bool) -> Result<(EnvFilter, EnvFilter), SetupError> {
+    let directive = |level: &str| {
+        format!("{}={level}", crate::old::SPAN_TARGET)
+            .parse()
+            .expect("invalid directive")
+    };
+    Ok((
+        env_filter(strict)?.add_directive(directive("info")),
+        env_filter(strict)?.add_directive(directive("off")),
+    ))
+}
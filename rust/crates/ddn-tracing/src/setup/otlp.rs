@@ -0,0 +1,387 @@
+//! Configures the OTLP exporter.
+
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use opentelemetry_sdk::export::trace::SpanExporter;
+use tonic::metadata::{Ascii, KeyAndValueRef, MetadataKey, MetadataMap, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+use tonic::Status;
+
+use super::SetupError;
+
+pub use opentelemetry_otlp::Compression;
+
+/// Where spans are sent if no endpoint is configured.
+const DEFAULT_ENDPOINT: &str = "http://localhost:4317";
+
+/// How long to wait for each export if no timeout is configured.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Environment variables which configure each setting when it is not set
+// explicitly, in order of precedence.
+const ENDPOINT_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+];
+const TIMEOUT_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_TIMEOUT",
+    "OTEL_EXPORTER_OTLP_TIMEOUT",
+];
+const COMPRESSION_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_COMPRESSION",
+    "OTEL_EXPORTER_OTLP_COMPRESSION",
+];
+const HEADERS_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_HEADERS",
+    "OTEL_EXPORTER_OTLP_HEADERS",
+];
+const CERTIFICATE_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_CERTIFICATE",
+    "OTEL_EXPORTER_OTLP_CERTIFICATE",
+];
+const CLIENT_CERTIFICATE_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_CLIENT_CERTIFICATE",
+    "OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE",
+];
+const CLIENT_KEY_ENV_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_TRACES_CLIENT_KEY",
+    "OTEL_EXPORTER_OTLP_CLIENT_KEY",
+];
+
+/// Configures an OTLP exporter.
+///
+/// Every setting can also be configured by the standard environment
+/// variables, which are only used for settings which are not set here, so
+/// that several exporters can be configured independently:
+///
+///   * https://opentelemetry.io/docs/languages/sdk-configuration/otlp-exporter/
+///
+/// Note that, as per the specification, `OTEL_EXPORTER_OTLP_TIMEOUT` is in
+/// milliseconds, and certificates and keys are passed as paths to PEM files.
+#[derive(Clone, Default)]
+pub struct OtlpExporter {
+    endpoint: Option<String>,
+    timeout: Option<Duration>,
+    compression: Option<Compression>,
+    headers: Vec<(String, String)>,
+    ca_certificate: Option<Vec<u8>>,
+    client_identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl OtlpExporter {
+    /// Creates an exporter configured through the environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the endpoint, instead of `OTEL_EXPORTER_OTLP_ENDPOINT`.
+    ///
+    /// Use an `https` URL to connect over TLS.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets how long to wait for each export. This defaults to 10 seconds.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Compresses requests.
+    #[must_use]
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Adds a header to every request, e.g. for authentication.
+    ///
+    /// If any headers are set here, `OTEL_EXPORTER_OTLP_HEADERS` is ignored.
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Trusts the given PEM-encoded CA certificate, in addition to the
+    /// system's root certificates, when connecting over TLS.
+    #[must_use]
+    pub fn with_ca_certificate(mut self, certificate_pem: impl Into<Vec<u8>>) -> Self {
+        self.ca_certificate = Some(certificate_pem.into());
+        self
+    }
+
+    /// Authenticates with the given PEM-encoded client certificate and
+    /// private key when connecting over TLS, i.e. mutual TLS.
+    #[must_use]
+    pub fn with_client_identity(
+        mut self,
+        certificate_pem: impl Into<Vec<u8>>,
+        key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.client_identity = Some((certificate_pem.into(), key_pem.into()));
+        self
+    }
+
+    pub(super) fn build(self) -> Result<Box<dyn SpanExporter>, SetupError> {
+        let endpoint = self.resolve_endpoint()?;
+        let timeout = self.resolve_timeout()?;
+        let compression = self.resolve_compression()?;
+        let headers = self.resolve_headers()?;
+        let tls_config = self.resolve_tls_config()?;
+
+        let mut channel = tonic::transport::Channel::from_shared(endpoint.clone())
+            .map_err(|source| SetupError::InvalidEndpoint { endpoint, source })?
+            .timeout(timeout);
+        if let Some(tls_config) = tls_config {
+            channel = channel
+                .tls_config(tls_config)
+                .map_err(|source| SetupError::InvalidTlsConfig { source })?;
+        }
+
+        // We build the channel ourselves, so the exporter's own handling of
+        // the endpoint and timeout is not used.
+        let mut exporter = opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_channel(channel.connect_lazy());
+        if let Some(headers) = headers {
+            exporter = exporter.with_interceptor(headers);
+        }
+        if let Some(compression) = compression {
+            exporter = exporter.with_compression(compression);
+        }
+        let exporter = exporter
+            .build_span_exporter()
+            .map_err(|source| SetupError::Exporter { source })?;
+        Ok(Box::new(exporter))
+    }
+
+    /// Resolves the endpoint, checking that it is a valid URI, whether it was
+    /// passed explicitly or through the environment.
+    fn resolve_endpoint(&self) -> Result<String, SetupError> {
+        if let Some(endpoint) = &self.endpoint {
+            return match endpoint.parse::<http::Uri>() {
+                Ok(_) => Ok(endpoint.clone()),
+                Err(source) => Err(SetupError::InvalidEndpoint {
+                    endpoint: endpoint.clone(),
+                    source,
+                }),
+            };
+        }
+        if let Some((name, value)) = env_var(ENDPOINT_ENV_VARS) {
+            return match value.parse::<http::Uri>() {
+                Ok(_) => Ok(value),
+                Err(source) => Err(SetupError::InvalidEnvVar {
+                    name,
+                    value,
+                    source: source.into(),
+                }),
+            };
+        }
+        Ok(DEFAULT_ENDPOINT.to_owned())
+    }
+
+    fn resolve_timeout(&self) -> Result<Duration, SetupError> {
+        if let Some(timeout) = self.timeout {
+            return Ok(timeout);
+        }
+        match env_var(TIMEOUT_ENV_VARS) {
+            Some((name, value)) => match value.parse() {
+                Ok(milliseconds) => Ok(Duration::from_millis(milliseconds)),
+                Err(source) => Err(SetupError::InvalidEnvVar {
+                    name,
+                    value,
+                    source: Box::new(source),
+                }),
+            },
+            None => Ok(DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Resolves the compression. If it is set through the environment, the
+    /// exporter reads it itself, so we only check it here.
+    fn resolve_compression(&self) -> Result<Option<Compression>, SetupError> {
+        if self.compression.is_some() {
+            return Ok(self.compression);
+        }
+        match env_var(COMPRESSION_ENV_VARS) {
+            Some((name, value)) => match value.parse::<Compression>() {
+                Ok(_) => Ok(None),
+                Err(source) => Err(SetupError::InvalidEnvVar {
+                    name,
+                    value,
+                    source: Box::new(source),
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Converts the configured headers to gRPC metadata, if there are any.
+    ///
+    /// Otherwise, headers from the environment are added by the exporter
+    /// itself, so we only check them here.
+    fn resolve_headers(&self) -> Result<Option<Headers>, SetupError> {
+        let env_headers = env_var(HEADERS_ENV_VARS);
+        if self.headers.is_empty() {
+            if let Some((name, value)) = env_headers {
+                if let Some(header) = env_header_keys(&value).find_map(Result::err) {
+                    return Err(SetupError::InvalidEnvVar {
+                        name,
+                        value: value.clone(),
+                        source: format!("invalid header {header:?}").into(),
+                    });
+                }
+            }
+            return Ok(None);
+        }
+
+        let mut metadata = MetadataMap::new();
+        for (name, value) in &self.headers {
+            let key = MetadataKey::from_bytes(name.as_bytes()).map_err(|source| {
+                SetupError::InvalidHeader {
+                    name: name.clone(),
+                    source: source.into(),
+                }
+            })?;
+            let value = MetadataValue::try_from(value.as_str()).map_err(|source| {
+                SetupError::InvalidHeader {
+                    name: name.clone(),
+                    source: source.into(),
+                }
+            })?;
+            metadata.append(key, value);
+        }
+        // The exporter adds headers from the environment regardless, so we
+        // remove them again before adding our own.
+        let ignored = env_headers
+            .map(|(_, value)| env_header_keys(&value).filter_map(Result::ok).collect())
+            .unwrap_or_default();
+        Ok(Some(Headers { metadata, ignored }))
+    }
+
+    /// Builds the TLS configuration, if any certificates are configured.
+    ///
+    /// Connecting to an `https` endpoint uses the system's root certificates
+    /// even without this.
+    fn resolve_tls_config(&self) -> Result<Option<ClientTlsConfig>, SetupError> {
+        let ca_certificate = match (&self.ca_certificate, env_var(CERTIFICATE_ENV_VARS)) {
+            (Some(ca_certificate), _) => Some(ca_certificate.clone()),
+            (None, Some(var)) => Some(read_file(var)?),
+            (None, None) => None,
+        };
+        let client_identity = if self.client_identity.is_some() {
+            self.client_identity.clone()
+        } else {
+            match (
+                env_var(CLIENT_CERTIFICATE_ENV_VARS),
+                env_var(CLIENT_KEY_ENV_VARS),
+            ) {
+                (Some(certificate_var), Some(key_var)) => {
+                    Some((read_file(certificate_var)?, read_file(key_var)?))
+                }
+                (Some((name, value)), None) | (None, Some((name, value))) => {
+                    return Err(SetupError::InvalidEnvVar {
+                        name,
+                        value,
+                        source: "the client certificate and key must be set together".into(),
+                    });
+                }
+                (None, None) => None,
+            }
+        };
+
+        if ca_certificate.is_none() && client_identity.is_none() {
+            return Ok(None);
+        }
+        let mut tls_config = ClientTlsConfig::new();
+        if let Some(ca_certificate) = ca_certificate {
+            tls_config = tls_config.ca_certificate(Certificate::from_pem(ca_certificate));
+        }
+        if let Some((certificate, key)) = client_identity {
+            tls_config = tls_config.identity(Identity::from_pem(certificate, key));
+        }
+        Ok(Some(tls_config))
+    }
+}
+
+/// Header values and keys may be secret, so we only show header names and
+/// whether certificates are set.
+impl std::fmt::Debug for OtlpExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OtlpExporter")
+            .field("endpoint", &self.endpoint)
+            .field("timeout", &self.timeout)
+            .field("compression", &self.compression)
+            .field(
+                "headers",
+                &self
+                    .headers
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("ca_certificate", &self.ca_certificate.is_some())
+            .field("client_identity", &self.client_identity.is_some())
+            .finish()
+    }
+}
+
+/// Replaces the headers which the exporter takes from the environment with
+/// the configured ones, on every request.
+#[derive(Clone)]
+struct Headers {
+    metadata: MetadataMap,
+    ignored: Vec<MetadataKey<Ascii>>,
+}
+
+impl Interceptor for Headers {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let request_metadata = request.metadata_mut();
+        for key in &self.ignored {
+            request_metadata.remove(key);
+        }
+        for key_and_value in self.metadata.iter() {
+            if let KeyAndValueRef::Ascii(key, value) = key_and_value {
+                request_metadata.append(key.clone(), value.clone());
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// Parses the header names from the value of `OTEL_EXPORTER_OTLP_HEADERS`,
+/// returning each invalid header as an error.
+fn env_header_keys(value: &str) -> impl Iterator<Item = Result<MetadataKey<Ascii>, &str>> {
+    value
+        .split(',')
+        .filter(|header| !header.trim().is_empty())
+        .map(|header| {
+            header
+                .split_once('=')
+                .and_then(|(key, _)| MetadataKey::from_bytes(key.trim().as_bytes()).ok())
+                .ok_or(header)
+        })
+}
+
+/// Gets the first of the given environment variables which is set.
+fn env_var(names: [&'static str; 2]) -> Option<(&'static str, String)> {
+    names
+        .into_iter()
+        .find_map(|name| env::var(name).ok().map(|value| (name, value)))
+}
+
+/// Reads the file at the path given by an environment variable.
+fn read_file((name, value): (&'static str, String)) -> Result<Vec<u8>, SetupError> {
+    fs::read(&value).map_err(|source| SetupError::InvalidEnvVar {
+        name,
+        value,
+        source: source.into(),
+    })
+}
@@ -0,0 +1,271 @@
+//! Scrubs personal data and secrets from span attributes before export.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use opentelemetry::metrics::{Counter, Meter, Unit};
+use opentelemetry::trace::TraceResult;
+use opentelemetry::{Array, Context, Key, KeyValue, StringValue, Value};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{Span, SpanProcessor};
+use regex::{Captures, Regex};
+
+use super::SetupError;
+
+const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+/// Checks a match before it is replaced, so that patterns which are too broad
+/// on their own do not replace everything that looks similar.
+type Check = fn(&str) -> bool;
+
+/// Patterns for data which commonly ends up in attributes by accident.
+const COMMON_PATTERNS: [(&str, Option<Check>); 3] = [
+    // email addresses
+    (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", None),
+    // bearer tokens, e.g. from an `Authorization` header
+    (r"(?i)bearer\s+[A-Za-z0-9._~+/-]+=*", None),
+    // payment card numbers, optionally separated by spaces or dashes, which
+    // must pass the Luhn check so that other long numbers, such as IDs and
+    // timestamps, are mostly left alone
+    (r"\b(?:\d[ -]?){12,18}\d\b", Some(passes_luhn_check)),
+];
+
+/// Configures how span and event attributes are scrubbed before they reach
+/// any exporter.
+///
+/// Attributes with a sensitive key have their whole value replaced. Other
+/// string values have each match of any pattern replaced. Only exported
+/// spans are scrubbed, not the log lines written to standard output.
+///
+/// A clone of this shares the count of redactions, so keep one to read it
+/// with [`Scrubber::redactions`]. The count is also published as the
+/// `ddn_tracing.attributes.redacted` metric.
+#[derive(Clone, Debug)]
+pub struct Scrubber {
+    patterns: Vec<(String, Option<Check>)>,
+    sensitive_keys: HashSet<Key>,
+    replacement: String,
+    redactions: Arc<AtomicU64>,
+}
+
+impl Default for Scrubber {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            sensitive_keys: HashSet::new(),
+            replacement: DEFAULT_REPLACEMENT.to_owned(),
+            redactions: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Scrubber {
+    /// Creates a scrubber with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces matches of the given regular expression. The pattern is
+    /// checked when tracing is set up.
+    #[must_use]
+    pub fn with_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push((pattern.into(), None));
+        self
+    }
+
+    /// Replaces email addresses, bearer tokens and payment card numbers.
+    ///
+    /// Card numbers are sequences of 13 to 19 digits which pass the Luhn
+    /// check. About one in ten other numbers of that length pass it too, so
+    /// these are redacted as well.
+    #[must_use]
+    pub fn with_common_patterns(mut self) -> Self {
+        self.patterns.extend(
+            COMMON_PATTERNS
+                .into_iter()
+                .map(|(pattern, check)| (pattern.to_owned(), check)),
+        );
+        self
+    }
+
+    /// Replaces the whole value of attributes with exactly this key.
+    #[must_use]
+    pub fn with_sensitive_key(mut self, key: impl Into<Key>) -> Self {
+        self.sensitive_keys.insert(key.into());
+        self
+    }
+
+    /// Sets the text which replaces scrubbed data. This defaults to
+    /// `[REDACTED]`.
+    #[must_use]
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// The number of values or matches replaced so far.
+    pub fn redactions(&self) -> u64 {
+        self.redactions.load(Ordering::Relaxed)
+    }
+
+    /// Builds a span processor which scrubs spans, and then passes them on.
+    pub(super) fn processor<P: SpanProcessor>(
+        self,
+        meter: &Meter,
+        inner: P,
+    ) -> Result<ScrubbingProcessor<P>, SetupError> {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|(pattern, check)| {
+                Regex::new(pattern)
+                    .map(|regex| (regex, *check))
+                    .map_err(|source| SetupError::InvalidScrubPattern {
+                        pattern: pattern.clone(),
+                        source,
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        let metric = meter
+            .u64_counter("ddn_tracing.attributes.redacted")
+            .with_description("The number of attribute values or matches scrubbed.")
+            .with_unit(Unit::new("{redaction}"))
+            .init();
+        Ok(ScrubbingProcessor {
+            patterns,
+            sensitive_keys: self.sensitive_keys,
+            replacement: self.replacement.into(),
+            redactions: self.redactions,
+            metric,
+            inner,
+        })
+    }
+}
+
+/// Scrubs each span, and then passes it on.
+///
+/// This comes before spans are passed on to each exporter, so that they are
+/// scrubbed once, however many exporters there are, and each redaction is
+/// only counted once.
+#[derive(Debug)]
+pub(super) struct ScrubbingProcessor<P> {
+    patterns: Vec<(Regex, Option<Check>)>,
+    sensitive_keys: HashSet<Key>,
+    replacement: StringValue,
+    redactions: Arc<AtomicU64>,
+    metric: Counter<u64>,
+    inner: P,
+}
+
+impl<P> ScrubbingProcessor<P> {
+    /// Scrubs attributes in place, returning the number of redactions.
+    fn scrub(&self, attributes: &mut [KeyValue]) -> u64 {
+        let mut redactions = 0;
+        for attribute in attributes {
+            if self.sensitive_keys.contains(&attribute.key) {
+                attribute.value = Value::String(self.replacement.clone());
+                redactions += 1;
+                continue;
+            }
+            match &mut attribute.value {
+                Value::String(value) => {
+                    if let Some((scrubbed, count)) = self.scrub_string(value.as_str()) {
+                        *value = scrubbed;
+                        redactions += count;
+                    }
+                }
+                Value::Array(Array::String(values)) => {
+                    for value in values {
+                        if let Some((scrubbed, count)) = self.scrub_string(value.as_str()) {
+                            *value = scrubbed;
+                            redactions += count;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        redactions
+    }
+
+    /// Replaces every match of every pattern which passes its check, if
+    /// there are any.
+    fn scrub_string(&self, value: &str) -> Option<(StringValue, u64)> {
+        let mut scrubbed = None::<String>;
+        let mut count = 0;
+        for (pattern, check) in &self.patterns {
+            let current = scrubbed.as_deref().unwrap_or(value);
+            let mut matches = 0;
+            let replaced = pattern.replace_all(current, |captures: &Captures<'_>| {
+                let found = &captures[0];
+                if check.map_or(true, |check| check(found)) {
+                    matches += 1;
+                    self.replacement.as_str().to_owned()
+                } else {
+                    found.to_owned()
+                }
+            });
+            if matches > 0 {
+                count += matches;
+                scrubbed = Some(replaced.into_owned());
+            }
+        }
+        scrubbed.map(|scrubbed| (scrubbed.into(), count))
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ScrubbingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let mut redactions = self.scrub(&mut span.attributes);
+        for event in &mut span.events.events {
+            redactions += self.scrub(&mut event.attributes);
+        }
+        for link in &mut span.links.links {
+            redactions += self.scrub(&mut link.attributes);
+        }
+        if redactions > 0 {
+            self.redactions.fetch_add(redactions, Ordering::Relaxed);
+            self.metric.add(redactions, &[]);
+        }
+
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// Checks the Luhn checksum used by payment card numbers, ignoring spaces and
+/// dashes.
+fn passes_luhn_check(number: &str) -> bool {
+    let sum = number
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| {
+            let digit = u32::from(digit - b'0');
+            if index % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum::<u32>();
+    sum % 10 == 0
+}
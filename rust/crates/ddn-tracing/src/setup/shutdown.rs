@@ -0,0 +1,251 @@
+//! Flushes and shuts down a tracing pipeline.
+
+use std::io::{self, Write};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use opentelemetry::metrics::MetricsError;
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::TracerProvider;
+use tokio::runtime::RuntimeFlavor;
+use tokio::task::JoinHandle;
+use tracing::Metadata;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+
+use crate::export::ExportStats;
+
+/// How long dropping a pipeline waits for pending spans to be exported.
+const DROP_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of shutting down tracing.
+///
+/// Spans, metrics and log lines are all flushed, but only spans are counted,
+/// as metrics are exported periodically and logs are written synchronously.
+#[derive(Debug)]
+#[must_use]
+pub struct ShutdownReport {
+    /// The number of spans exported successfully since setup. With several
+    /// exporters, a span is counted once for each.
+    pub spans_exported: u64,
+    /// The number of spans which failed to export since setup. With several
+    /// exporters, a span is counted once for each.
+    pub spans_failed: u64,
+    /// The number of spans dropped since setup because an export queue was
+    /// full. With several exporters, a span is counted once for each.
+    pub spans_dropped: u64,
+    /// The number of spans written to a retry queue since setup. These are
+    /// also counted as exported or failed if they were retried before
+    /// shutdown, and are otherwise sent by the next process.
+    pub spans_spilled: u64,
+    /// Errors reported while flushing pending spans.
+    pub errors: Vec<TraceError>,
+    /// Errors reported while flushing metrics, if a meter provider was given
+    /// to [`super::Builder::with_meter_provider`].
+    pub metrics_errors: Vec<MetricsError>,
+    /// Errors reported while flushing log lines.
+    pub log_errors: Vec<io::Error>,
+    /// Whether the timeout elapsed before everything could be flushed. If
+    /// so, pending spans are lost, and are not counted as failures.
+    pub timed_out: bool,
+}
+
+impl ShutdownReport {
+    /// Whether every span was exported, and everything else flushed, as far
+    /// as we know.
+    pub fn is_complete(&self) -> bool {
+        self.spans_failed == 0
+            && self.spans_dropped == 0
+            && self.errors.is_empty()
+            && self.metrics_errors.is_empty()
+            && self.log_errors.is_empty()
+            && !self.timed_out
+    }
+}
+
+/// Everything which is flushed on shutdown.
+pub(super) struct Providers {
+    pub tracer_provider: TracerProvider,
+    /// The meter provider publishing export metrics, if we were given it.
+    pub meter_provider: Option<SdkMeterProvider>,
+    pub log_writer: LogWriter,
+}
+
+/// A log writer which can be shared between the fmt layer and the pipeline,
+/// so that it can be flushed on shutdown.
+#[derive(Clone)]
+pub(super) struct LogWriter(Arc<BoxMakeWriter>);
+
+impl LogWriter {
+    pub fn new(inner: BoxMakeWriter) -> Self {
+        Self(Arc::new(inner))
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = Box<dyn Write + 'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.0.make_writer()
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.0.make_writer_for(meta)
+    }
+}
+
+/// What went wrong while flushing.
+#[derive(Default)]
+struct FlushErrors {
+    traces: Vec<TraceError>,
+    metrics: Vec<MetricsError>,
+    logs: Vec<io::Error>,
+}
+
+/// The providers, their export statistics, and the task which warns about
+/// spans that could not be exported.
+///
+/// If this is dropped without being shut down, it will wait a bounded amount
+/// of time for pending spans instead, outside of current-thread runtimes.
+pub(super) struct Pipeline {
+    providers: Option<Providers>,
+    stats: ExportStats,
+    warnings: JoinHandle<()>,
+}
+
+impl Pipeline {
+    pub fn new(providers: Providers, stats: ExportStats, warnings: JoinHandle<()>) -> Self {
+        Self {
+            providers: Some(providers),
+            stats,
+            warnings,
+        }
+    }
+
+    pub fn tracer_provider(&self) -> Option<&TracerProvider> {
+        self.providers
+            .as_ref()
+            .map(|providers| &providers.tracer_provider)
+    }
+
+    /// Exports any pending spans, then flushes metrics and logs, waiting at
+    /// most `timeout` for everything to be sent.
+    pub async fn shutdown(&mut self, timeout: Duration) -> ShutdownReport {
+        self.warnings.abort();
+        let Some(providers) = self.providers.take() else {
+            return self.report(FlushErrors::default(), false);
+        };
+        let flush = tokio::task::spawn_blocking(move || flush_and_shut_down(providers));
+        match tokio::time::timeout(timeout, flush).await {
+            Ok(Ok(errors)) => self.report(errors, false),
+            Ok(Err(join_error)) => self.report(
+                FlushErrors {
+                    traces: vec![TraceError::Other(Box::new(join_error))],
+                    ..FlushErrors::default()
+                },
+                false,
+            ),
+            Err(_) => self.report(FlushErrors::default(), true),
+        }
+    }
+
+    fn report(&self, errors: FlushErrors, timed_out: bool) -> ShutdownReport {
+        ShutdownReport {
+            spans_exported: self.stats.exported(),
+            spans_failed: self.stats.failed(),
+            spans_dropped: self.stats.dropped(),
+            spans_spilled: self.stats.spilled(),
+            errors: errors.traces,
+            metrics_errors: errors.metrics,
+            log_errors: errors.logs,
+            timed_out,
+        }
+    }
+}
+
+/// Exports pending spans, then shuts down the span processors, and then
+/// flushes metrics, which now include the final span counts, and log lines.
+/// This blocks until the exporters are done.
+///
+/// Each span processor is flushed on its own thread, so that a slow exporter
+/// does not hold up the others. The meter provider is only flushed, not shut
+/// down, as it may be used elsewhere.
+fn flush_and_shut_down(providers: Providers) -> FlushErrors {
+    let Providers {
+        tracer_provider,
+        meter_provider,
+        log_writer,
+    } = providers;
+    let traces = thread::scope(|scope| {
+        let flushes = tracer_provider
+            .span_processors()
+            .iter()
+            .map(|processor| scope.spawn(|| processor.force_flush()))
+            .collect::<Vec<_>>();
+        flushes
+            .into_iter()
+            .filter_map(|flush| match flush.join() {
+                Ok(result) => result.err(),
+                Err(_) => Some(TraceError::from("flushing span processor panicked")),
+            })
+            .collect()
+    });
+    // Dropping the last reference to the provider shuts down its processors.
+    drop(tracer_provider);
+    let metrics = meter_provider
+        .and_then(|meter_provider| meter_provider.force_flush().err())
+        .into_iter()
+        .collect();
+    let logs = log_writer.make_writer().flush().err().into_iter().collect();
+    FlushErrors {
+        traces,
+        metrics,
+        logs,
+    }
+}
+
+/// A fallback for when [`Pipeline::shutdown`] is not called.
+///
+/// This cannot be asynchronous, so flushing happens on a separate thread,
+/// which is abandoned if it takes too long.
+///
+/// In a current-thread runtime, the export tasks cannot run while we wait, so
+/// the flush is left to finish in the background, if the runtime keeps
+/// running, rather than blocking until the timeout.
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.warnings.abort();
+        let Some(providers) = self.providers.take() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(flush_and_shut_down(providers));
+        });
+        let in_current_thread_runtime = tokio::runtime::Handle::try_current()
+            .is_ok_and(|runtime| runtime.runtime_flavor() == RuntimeFlavor::CurrentThread);
+        if in_current_thread_runtime {
+            tracing::warn!(
+                "tracing was dropped inside a current-thread runtime without being shut down, \
+                 so pending spans may not be exported",
+            );
+            return;
+        }
+        let report = match receiver.recv_timeout(DROP_SHUTDOWN_TIMEOUT) {
+            Ok(errors) => self.report(errors, false),
+            Err(_) => self.report(FlushErrors::default(), true),
+        };
+        if !report.is_complete() {
+            tracing::warn!(
+                spans_failed = report.spans_failed,
+                spans_dropped = report.spans_dropped,
+                errors = ?report.errors,
+                metrics_errors = ?report.metrics_errors,
+                log_errors = ?report.log_errors,
+                timed_out = report.timed_out,
+                "some spans could not be exported on shutdown",
+            );
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Filters spans down to those which users should see, for customer-facing
+//! exporters.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use opentelemetry::trace::{Span as _, SpanId, TraceContextExt, TraceId, TraceResult};
+use opentelemetry::{Context, Value};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{Span, SpanProcessor};
+
+use crate::old::SpanVisibility;
+
+/// The attribute set by [`crate::old::Tracer`] to record a span's
+/// visibility.
+const VISIBILITY_ATTRIBUTE: &str = "internal.visibility";
+
+/// The most spans held back at once. Beyond this, the oldest traces are sent
+/// on without waiting for their local root spans.
+const MAX_PENDING_SPANS: usize = 4096;
+
+/// The most traces held back at once, counting those with only internal
+/// spans. Beyond this, the oldest are sent on as above.
+const MAX_PENDING_TRACES: usize = 1024;
+
+/// The most local roots tracked at once. Beyond this, the oldest are
+/// forgotten, so that spans which never end do not use memory forever, and
+/// their traces are eventually sent on as above.
+const MAX_LOCAL_ROOTS: usize = 4096;
+
+/// How many traces whose local root has ended are remembered, so that spans
+/// which end later are forwarded straight away.
+const MAX_COMPLETED_TRACES: usize = 1024;
+
+/// Wraps a span processor, forwarding only spans whose visibility is `user`.
+///
+/// The children of a dropped span are re-parented onto its nearest
+/// user-visible ancestor. As children end before their parents, spans are
+/// held back until the local root of their trace ends, i.e. the span whose
+/// parent is remote, or which has no parent.
+///
+/// Spans which end after their local root are forwarded straight away,
+/// without being re-parented.
+///
+/// A span is only re-parented past internal ancestors which have ended. If
+/// one has not, e.g. because its trace was sent on early, or the span ended
+/// after its local root, the span keeps an internal parent, which is never
+/// exported, so it appears to have a missing parent.
+#[derive(Debug)]
+pub(super) struct UserVisibleProcessor<P> {
+    inner: P,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The spans which started without a local parent, and have not ended.
+    local_roots: HashSet<SpanId>,
+    /// The local roots, oldest first, including some which have ended.
+    local_root_order: VecDeque<SpanId>,
+    traces: HashMap<TraceId, PendingTrace>,
+    /// The traces with pending spans, oldest first.
+    order: VecDeque<TraceId>,
+    pending_spans: usize,
+    /// The traces whose local root has ended recently.
+    completed: HashSet<TraceId>,
+    /// The completed traces, oldest first.
+    completed_order: VecDeque<TraceId>,
+}
+
+impl State {
+    fn add_local_root(&mut self, span_id: SpanId, trace_id: TraceId) {
+        self.local_roots.insert(span_id);
+        self.local_root_order.push_back(span_id);
+        while self.local_root_order.len() > MAX_LOCAL_ROOTS {
+            if let Some(oldest) = self.local_root_order.pop_front() {
+                self.local_roots.remove(&oldest);
+            }
+        }
+        // The trace has a local root again, e.g. from another request with
+        // the same remote parent, so its spans are held back again.
+        if self.completed.remove(&trace_id) {
+            self.completed_order
+                .retain(|completed| *completed != trace_id);
+        }
+    }
+
+    fn complete(&mut self, trace_id: TraceId) {
+        if self.completed.insert(trace_id) {
+            self.completed_order.push_back(trace_id);
+        }
+        while self.completed_order.len() > MAX_COMPLETED_TRACES {
+            if let Some(oldest) = self.completed_order.pop_front() {
+                self.completed.remove(&oldest);
+            }
+        }
+    }
+
+    /// Removes a pending trace, returning its spans.
+    fn take_trace(&mut self, trace_id: TraceId) -> Vec<SpanData> {
+        let Some(trace) = self.traces.remove(&trace_id) else {
+            return Vec::new();
+        };
+        self.order.retain(|pending| *pending != trace_id);
+        self.pending_spans -= trace.user_spans.len();
+        trace.resolve()
+    }
+
+    /// Removes the oldest pending traces until within the limits, returning
+    /// their spans.
+    fn evict(&mut self) -> Vec<SpanData> {
+        let mut evicted = Vec::new();
+        while self.pending_spans > MAX_PENDING_SPANS || self.traces.len() > MAX_PENDING_TRACES {
+            let Some(oldest) = self.order.front().copied() else {
+                break;
+            };
+            evicted.extend(self.take_trace(oldest));
+        }
+        evicted
+    }
+}
+
+/// The spans of a trace which have ended before its local root.
+#[derive(Debug, Default)]
+struct PendingTrace {
+    user_spans: Vec<SpanData>,
+    /// The parent of each internal span.
+    internal_parents: HashMap<SpanId, SpanId>,
+}
+
+impl PendingTrace {
+    /// Re-parents the user spans onto their nearest user-visible ancestors,
+    /// as far as those ancestors have ended.
+    fn resolve(self) -> Vec<SpanData> {
+        let mut user_spans = self.user_spans;
+        for span in &mut user_spans {
+            while let Some(parent) = self.internal_parents.get(&span.parent_span_id) {
+                span.parent_span_id = *parent;
+            }
+        }
+        user_spans
+    }
+}
+
+impl<P> UserVisibleProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl<P: SpanProcessor> UserVisibleProcessor<P> {
+    /// Forwards every pending span, re-parenting as far as possible.
+    fn flush_pending(&self) {
+        let traces = {
+            let mut state = self.state.lock().unwrap();
+            state.order.clear();
+            state.pending_spans = 0;
+            std::mem::take(&mut state.traces)
+        };
+        for trace in traces.into_values() {
+            for span in trace.resolve() {
+                self.inner.on_end(span);
+            }
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for UserVisibleProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let parent = cx.span().span_context().clone();
+        if !parent.is_valid() || parent.is_remote() {
+            let span_context = span.span_context();
+            self.state
+                .lock()
+                .unwrap()
+                .add_local_root(span_context.span_id(), span_context.trace_id());
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let span_id = span.span_context.span_id();
+        let trace_id = span.span_context.trace_id();
+        let mut ready = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            let is_local_root = state.local_roots.remove(&span_id);
+            if !span.span_context.is_sampled() {
+                return;
+            }
+
+            if state.completed.contains(&trace_id) {
+                if is_user_visible(&span) {
+                    ready.push(span);
+                }
+            } else {
+                if !state.traces.contains_key(&trace_id) {
+                    state.order.push_back(trace_id);
+                }
+                let trace = state.traces.entry(trace_id).or_default();
+                if is_user_visible(&span) {
+                    trace.user_spans.push(span);
+                    state.pending_spans += 1;
+                } else {
+                    trace.internal_parents.insert(span_id, span.parent_span_id);
+                }
+            }
+
+            if is_local_root {
+                ready.extend(state.take_trace(trace_id));
+                state.complete(trace_id);
+            }
+            ready.extend(state.evict());
+        }
+        for span in ready {
+            self.inner.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.flush_pending();
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.flush_pending();
+        self.inner.shutdown()
+    }
+}
+
+fn is_user_visible(span: &SpanData) -> bool {
+    span.attributes.iter().any(|attribute| {
+        attribute.key.as_str() == VISIBILITY_ATTRIBUTE
+            && matches!(&attribute.value, Value::String(value) if value.as_str() == SpanVisibility::User.to_string())
+    })
+}
@@ -0,0 +1,58 @@
+//! Configures batching through the environment, so this is kept apart from
+//! other tests, which would otherwise see the same variables.
+
+use std::time::Duration;
+
+use ddn_tracing::export::{ExportResult, SpanData, SpanExporter};
+use ddn_tracing::setup::{BatchSettings, Builder, Exporter, ScopedTracing};
+use ddn_tracing::tracing;
+use futures_util::future::BoxFuture;
+use memory_collector::SHUTDOWN_TIMEOUT;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prefers_explicit_settings_to_the_environment() -> anyhow::Result<()> {
+    std::env::set_var("OTEL_BSP_MAX_QUEUE_SIZE", "1");
+
+    // The queue is large enough for every span.
+    let explicit = builder()
+        .with_batch_settings(BatchSettings::new().with_max_queue_size(3))
+        .build()?;
+    emit_spans(&explicit, 3);
+    let report = explicit.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert_eq!(report.spans_exported, 3, "Unexpected report: {report:?}");
+    assert_eq!(report.spans_dropped, 0, "Unexpected report: {report:?}");
+
+    // The environment is used when nothing is set explicitly.
+    let fallback = builder().build()?;
+    emit_spans(&fallback, 3);
+    let report = fallback.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert_eq!(report.spans_exported, 1, "Unexpected report: {report:?}");
+    assert_eq!(report.spans_dropped, 2, "Unexpected report: {report:?}");
+
+    Ok(())
+}
+
+fn builder() -> Builder {
+    Builder::new("test", "1.0.0").with_exporter(Exporter::custom(SlowExporter))
+}
+
+fn emit_spans(scoped: &ScopedTracing, count: usize) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        for _ in 0..count {
+            tracing::info_span!("span").in_scope(|| {});
+        }
+    });
+}
+
+/// Keeps spans in the queue while they are exported.
+#[derive(Debug)]
+struct SlowExporter;
+
+impl SpanExporter for SlowExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        })
+    }
+}
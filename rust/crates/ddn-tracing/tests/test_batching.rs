@@ -0,0 +1,185 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use ddn_tracing::export::{ExportResult, SpanData, SpanExporter};
+use ddn_tracing::setup::{BatchSettings, Builder, Exporter, ScopedTracing};
+use ddn_tracing::tracing;
+use futures_util::future::BoxFuture;
+use memory_collector::{SharedBuffer, SHUTDOWN_TIMEOUT};
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry_sdk::metrics::data::{ResourceMetrics, Sum, Temporality};
+use opentelemetry_sdk::metrics::reader::{AggregationSelector, MetricReader, TemporalitySelector};
+use opentelemetry_sdk::metrics::{Aggregation, InstrumentKind, ManualReader, Pipeline};
+use opentelemetry_sdk::Resource;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drops_spans_when_the_queue_is_full() -> anyhow::Result<()> {
+    let scoped = builder_with_a_tiny_queue().build()?;
+    emit_spans(&scoped, 3);
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(!report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_exported, 1);
+    assert_eq!(report.spans_dropped, 2);
+    assert_eq!(report.spans_failed, 0);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn publishes_export_counters_as_metrics() -> anyhow::Result<()> {
+    let reader = SharedReader(Arc::new(ManualReader::builder().build()));
+    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+
+    let scoped = builder_with_a_tiny_queue()
+        .with_meter(meter_provider.meter("test"))
+        .build()?;
+    emit_spans(&scoped, 3);
+    let _ = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    let mut metrics = ResourceMetrics {
+        resource: Resource::empty(),
+        scope_metrics: Vec::new(),
+    };
+    reader.collect(&mut metrics)?;
+    assert_eq!(sum(&metrics, "ddn_tracing.spans.exported"), Some(1));
+    assert_eq!(sum(&metrics, "ddn_tracing.spans.dropped"), Some(2));
+    assert_eq!(sum(&metrics, "ddn_tracing.spans.failed"), None);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn counts_timed_out_exports_as_failed() -> anyhow::Result<()> {
+    let logs = SharedBuffer::default();
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(HangingExporter::default()))
+        .with_batch_settings(
+            BatchSettings::new()
+                .with_max_queue_size(1)
+                .with_scheduled_delay(Duration::from_millis(10))
+                .with_max_export_timeout(Duration::from_millis(100)),
+        )
+        .with_export_warning_interval(Duration::from_millis(50))
+        .with_log_writer({
+            let logs = logs.clone();
+            move || logs.clone()
+        })
+        .build()?;
+    emit_spans(&scoped, 1);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    // The timed-out span no longer takes up the queue.
+    emit_spans(&scoped, 1);
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert_eq!(report.spans_exported, 1, "Unexpected report: {report:?}");
+    assert_eq!(report.spans_failed, 1, "Unexpected report: {report:?}");
+    assert_eq!(report.spans_dropped, 0, "Unexpected report: {report:?}");
+    let logs = logs.contents();
+    let warning = logs
+        .lines()
+        .find(|line| line.contains("some spans could not be exported"))
+        .unwrap_or_else(|| panic!("No warning was logged:\n{logs}"));
+    assert!(
+        warning.contains(r#""spans_failed":1"#),
+        "Unexpected warning: {warning}"
+    );
+
+    Ok(())
+}
+
+/// Only one span fits in the queue, and it stays there while it is slowly
+/// exported.
+fn builder_with_a_tiny_queue() -> Builder {
+    Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(SlowExporter))
+        .with_batch_settings(BatchSettings::new().with_max_queue_size(1))
+}
+
+fn emit_spans(scoped: &ScopedTracing, count: usize) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        for _ in 0..count {
+            tracing::info_span!("span").in_scope(|| {});
+        }
+    });
+}
+
+#[derive(Debug)]
+struct SlowExporter;
+
+impl SpanExporter for SlowExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(())
+        })
+    }
+}
+
+/// Never finishes exporting its first batch, and exports the rest
+/// immediately.
+#[derive(Debug, Default)]
+struct HangingExporter {
+    exports: usize,
+}
+
+impl SpanExporter for HangingExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        self.exports += 1;
+        if self.exports == 1 {
+            Box::pin(futures_util::future::pending())
+        } else {
+            Box::pin(async { Ok(()) })
+        }
+    }
+}
+
+/// Sums the data points of a counter, if it has been recorded.
+fn sum(metrics: &ResourceMetrics, name: &str) -> Option<u64> {
+    metrics
+        .scope_metrics
+        .iter()
+        .flat_map(|scope_metrics| &scope_metrics.metrics)
+        .find(|metric| metric.name == name)
+        .and_then(|metric| metric.data.as_any().downcast_ref::<Sum<u64>>())
+        .map(|sum| sum.data_points.iter().map(|point| point.value).sum())
+}
+
+/// A metric reader which can be read from after it is given to a meter
+/// provider.
+#[derive(Clone, Debug)]
+struct SharedReader(Arc<ManualReader>);
+
+impl TemporalitySelector for SharedReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl AggregationSelector for SharedReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl MetricReader for SharedReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline);
+    }
+
+    fn collect(&self, metrics: &mut ResourceMetrics) -> opentelemetry::metrics::Result<()> {
+        self.0.collect(metrics)
+    }
+
+    fn force_flush(&self) -> opentelemetry::metrics::Result<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> opentelemetry::metrics::Result<()> {
+        self.0.shutdown()
+    }
+}
@@ -0,0 +1,77 @@
+use ddn_tracing::export::{ConsoleExporter, ConsoleFormat};
+use ddn_tracing::setup::{Builder, ScopedTracing};
+use ddn_tracing::tracing;
+use memory_collector::{SharedBuffer, SHUTDOWN_TIMEOUT};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prints_spans_as_a_tree() -> anyhow::Result<()> {
+    let output = SharedBuffer::default();
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(ConsoleExporter::with_writer(
+            ConsoleFormat::Tree,
+            output.clone(),
+        ))
+        .build()?;
+    emit_nested_spans(&scoped);
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let output = output.contents();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "Unexpected output:\n{output}");
+    assert!(
+        lines[0].starts_with("trace "),
+        "Unexpected output:\n{output}"
+    );
+    assert!(
+        lines[1].starts_with("  parent [") && lines[1].contains(" answer=42"),
+        "Unexpected output:\n{output}"
+    );
+    assert!(
+        lines[2].starts_with("    child ["),
+        "Unexpected output:\n{output}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prints_spans_as_otlp_json() -> anyhow::Result<()> {
+    let output = SharedBuffer::default();
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(ConsoleExporter::with_writer(
+            ConsoleFormat::OtlpJson,
+            output.clone(),
+        ))
+        .build()?;
+    emit_nested_spans(&scoped);
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let output = output.contents();
+    let mut span_names = Vec::new();
+    for line in output.lines() {
+        let traces_data: serde_json::Value = serde_json::from_str(line)?;
+        for resource_spans in traces_data["resourceSpans"].as_array().unwrap() {
+            for scope_spans in resource_spans["scopeSpans"].as_array().unwrap() {
+                for span in scope_spans["spans"].as_array().unwrap() {
+                    span_names.push(span["name"].as_str().unwrap().to_owned());
+                }
+            }
+        }
+    }
+    span_names.sort();
+    assert_eq!(span_names, vec!["child", "parent"]);
+
+    Ok(())
+}
+
+fn emit_nested_spans(scoped: &ScopedTracing) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("parent", answer = 42).in_scope(|| {
+            tracing::info_span!("child").in_scope(|| {});
+        });
+    });
+}
@@ -0,0 +1,43 @@
+//! Configures logging through `RUST_LOG`, so this is kept apart from other
+//! tests, which would otherwise see the same variable.
+
+use ddn_tracing::old::{global_tracer, SpanVisibility};
+use ddn_tracing::setup::{Builder, LogFormat, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::{SharedBuffer, SHUTDOWN_TIMEOUT};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_old_spans_whatever_the_log_level() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+    let logs = SharedBuffer::default();
+
+    std::env::set_var("RUST_LOG", "warn");
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .with_log_format(LogFormat::Json)
+        .with_log_writer({
+            let logs = logs.clone();
+            move || logs.clone()
+        })
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        global_tracer().in_span("request", "Request", SpanVisibility::User, || {
+            tracing::info_span!("filtered").in_scope(|| {});
+            tracing::info!("filtered");
+            tracing::warn!("logged");
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    assert_eq!(collector_state.read_span_names(), ["request"]);
+    let logs = logs.contents();
+    let lines = logs.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "Unexpected logs: {logs}");
+    assert!(lines[0].contains("logged"), "Unexpected logs: {logs}");
+    // The old span is not logged, as its name would be `span`.
+    assert!(!lines[0].contains("\"span\""), "Unexpected logs: {logs}");
+
+    Ok(())
+}
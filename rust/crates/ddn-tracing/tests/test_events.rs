@@ -0,0 +1,97 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ddn_tracing::event::Event;
+use ddn_tracing::old::{add_event_with_attributes_on_active_span, AttributeVisibility};
+use ddn_tracing::setup::{Builder, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::{attribute, proto, SHUTDOWN_TIMEOUT};
+use opentelemetry::trace::{Tracer, TracerProvider};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn records_events_with_attributes_and_timestamps() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span").in_scope(|| {
+            Event::new("cache miss")
+                .with_attribute("cache.key", "abc123")
+                .with_attribute("rows", 42)
+                .with_internal_attribute("plan", "HashJoin")
+                .with_timestamp(timestamp)
+                .record();
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    assert_eq!(span.events.len(), 1);
+    let event = &span.events[0];
+    assert_eq!(event.name, "cache miss");
+    assert_eq!(event.time_unix_nano, 1_700_000_000_000_000_000);
+    assert_eq!(
+        attribute(&event.attributes, "cache.key"),
+        Some(proto::any_value::Value::StringValue("abc123".to_owned()))
+    );
+    assert_eq!(
+        attribute(&event.attributes, "rows"),
+        Some(proto::any_value::Value::IntValue(42))
+    );
+    assert_eq!(
+        attribute(&event.attributes, "internal.plan"),
+        Some(proto::any_value::Value::StringValue("HashJoin".to_owned()))
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn records_events_on_the_active_span_with_the_old_api() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    let tracer = scoped
+        .tracer_provider()
+        .expect("tracer provider")
+        .tracer("test");
+    let before = SystemTime::now();
+    tracer.in_span("span", |_| {
+        add_event_with_attributes_on_active_span(
+            "query planned".to_owned(),
+            [
+                (AttributeVisibility::Default, "rows", 7.into()),
+                (AttributeVisibility::Internal, "plan", "Scan".into()),
+            ],
+            None,
+        );
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    assert_eq!(span.events.len(), 1);
+    let event = &span.events[0];
+    assert_eq!(event.name, "query planned");
+    assert!(
+        event.time_unix_nano >= u64::try_from(before.duration_since(UNIX_EPOCH)?.as_nanos())?,
+        "Unexpected timestamp: {event:?}"
+    );
+    assert_eq!(
+        attribute(&event.attributes, "rows"),
+        Some(proto::any_value::Value::IntValue(7))
+    );
+    assert_eq!(
+        attribute(&event.attributes, "internal.plan"),
+        Some(proto::any_value::Value::StringValue("Scan".to_owned()))
+    );
+
+    Ok(())
+}
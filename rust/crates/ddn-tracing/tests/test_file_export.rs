@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use ddn_tracing::export::FileExporter;
+use ddn_tracing::setup::{Builder, ScopedTracing};
+use ddn_tracing::tracing;
+use memory_collector::SHUTDOWN_TIMEOUT;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_spans_that_load_into_the_memory_collector() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(FileExporter::new(directory.path()))
+        .build()?;
+    emit_span(&scoped, "written");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let files = file_names(directory.path())?;
+    assert_eq!(files, vec!["traces.0.jsonl"]);
+
+    let collector_state = memory_collector::State::new();
+    collector_state.load_otlp_json_file(directory.path().join(&files[0]))?;
+    let spans = collector_state.read_spans();
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].name, "written");
+    assert_eq!(spans[0].trace_id.len(), 16);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rotates_files_and_keeps_the_newest() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+    // An old file, e.g. from a previous process.
+    fs::write(directory.path().join("traces.7.jsonl"), "")?;
+
+    // Every batch is larger than a byte, so each starts a new file.
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            FileExporter::new(directory.path())
+                .with_max_file_size(1)
+                .with_max_files(2),
+        )
+        .build()?;
+    for name in ["one", "two", "three"] {
+        emit_span(&scoped, name);
+        scoped
+            .tracer_provider()
+            .expect("tracer provider")
+            .force_flush();
+    }
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(
+        file_names(directory.path())?,
+        vec!["traces.10.jsonl", "traces.9.jsonl"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rotates_files_when_they_get_too_old() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            FileExporter::new(directory.path()).with_max_file_age(Duration::from_millis(500)),
+        )
+        .build()?;
+    for name in ["one", "two", "three"] {
+        emit_span(&scoped, name);
+        scoped
+            .tracer_provider()
+            .expect("tracer provider")
+            .force_flush();
+        // The first file is too old by the time the third span is written.
+        if name == "two" {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let files = file_names(directory.path())?;
+    assert_eq!(files, vec!["traces.0.jsonl", "traces.1.jsonl"]);
+    let lines = files
+        .iter()
+        .map(|file| {
+            Ok(fs::read_to_string(directory.path().join(file))?
+                .lines()
+                .count())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(lines, vec![2, 1]);
+
+    Ok(())
+}
+
+fn emit_span(scoped: &ScopedTracing, name: &'static str) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", otel.name = name).in_scope(|| {});
+    });
+}
+
+fn file_names(directory: &Path) -> anyhow::Result<Vec<String>> {
+    let mut names = fs::read_dir(directory)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
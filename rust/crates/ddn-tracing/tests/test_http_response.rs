@@ -0,0 +1,214 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use ddn_tracing::old::{
+    global_tracer, HttpRequestInfo, ResponseErrorKind, SpanVisibility, Traceable, TraceableError,
+    TraceableHttpResponse,
+};
+use ddn_tracing::setup::{Builder, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::{attribute, proto, SHUTDOWN_TIMEOUT};
+
+fn response(status: StatusCode) -> TraceableHttpResponse<axum::body::BoxBody> {
+    let response: Response = status.into_response();
+    TraceableHttpResponse::new(response, "/graphql")
+}
+
+#[test]
+fn classifies_2xx_and_3xx_as_success() {
+    for status in [
+        StatusCode::OK,
+        StatusCode::CREATED,
+        StatusCode::NO_CONTENT,
+        StatusCode::NOT_MODIFIED,
+    ] {
+        assert!(
+            response(status).get_error().is_none(),
+            "{status} is an error"
+        );
+    }
+
+    let error = response(StatusCode::NOT_FOUND)
+        .get_error()
+        .expect("expected an error");
+    assert_eq!(error.kind(), ResponseErrorKind::Client);
+    assert_eq!(
+        error.description(),
+        "HTTP request to /graphql failed with client error 404 Not Found"
+    );
+
+    let error = response(StatusCode::BAD_GATEWAY)
+        .get_error()
+        .expect("expected an error");
+    assert_eq!(error.kind(), ResponseErrorKind::Server);
+    assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[test]
+fn classifies_with_a_custom_classifier() {
+    let classify = |status: StatusCode| {
+        response(status)
+            .with_success_classifier(|status| status == StatusCode::OK)
+            .get_error()
+            .map(|error| error.kind())
+    };
+    assert_eq!(classify(StatusCode::OK), None);
+    assert_eq!(
+        classify(StatusCode::NOT_MODIFIED),
+        Some(ResponseErrorKind::Other)
+    );
+}
+
+#[test]
+fn classifies_with_a_capturing_classifier() {
+    // Statuses which are expected for this route, and so are not errors.
+    let expected = vec![StatusCode::OK, StatusCode::NOT_FOUND];
+    let classify = |status: StatusCode| {
+        let expected = expected.clone();
+        response(status)
+            .with_success_classifier(move |status| expected.contains(&status))
+            .get_error()
+            .map(|error| error.kind())
+    };
+    assert_eq!(classify(StatusCode::NOT_FOUND), None);
+    assert_eq!(
+        classify(StatusCode::CONFLICT),
+        Some(ResponseErrorKind::Client)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn records_the_status_code() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        global_tracer().in_span("request", "request", SpanVisibility::User, || {
+            response(StatusCode::CREATED)
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    let status_code = attribute(&span.attributes, "http.response.status_code");
+    assert_eq!(status_code, Some(proto::any_value::Value::IntValue(201)));
+    assert!(span.status.as_ref().map_or(true, |status| status.code
+        != proto::status::StatusCode::Error as i32));
+
+    Ok(())
+}
+
+#[test]
+fn takes_the_method_and_path_from_the_request() {
+    let request = axum::http::Request::post("/v1/graphql?query=1")
+        .body(())
+        .expect("invalid request");
+    let request_info = HttpRequestInfo::new(&request);
+    // Without a route, the path is not used, as it could make span names unbounded.
+    assert_eq!(request_info.span_name(), "POST");
+
+    let response =
+        TraceableHttpResponse::for_request(StatusCode::BAD_REQUEST.into_response(), request_info);
+    assert_eq!(response.path, "/v1/graphql");
+    let attributes = response
+        .attributes()
+        .into_iter()
+        .map(|attribute| (attribute.key.to_string(), attribute.value.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        attributes,
+        [
+            ("http.response.status_code".to_owned(), "400".to_owned()),
+            ("method".to_owned(), "POST".to_owned()),
+            // The query is left out, as it may contain secrets.
+            ("uri".to_owned(), "/v1/graphql".to_owned()),
+        ]
+    );
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn takes_the_route_from_axum() -> anyhow::Result<()> {
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    let app = axum::Router::new()
+        .route("/users/:id", get(|| async { StatusCode::NO_CONTENT }))
+        .route_layer(axum::middleware::from_fn(
+            |request: axum::http::Request<axum::body::Body>,
+             next: axum::middleware::Next<axum::body::Body>| async move {
+                let request_info = HttpRequestInfo::new(&request);
+                assert_eq!(request_info.route.as_deref(), Some("/users/:id"));
+                assert_eq!(request_info.span_name(), "GET /users/:id");
+                let response = next.run(request).await;
+                TraceableHttpResponse::for_request(response, request_info).response
+            },
+        ));
+    let response = app
+        .oneshot(axum::http::Request::get("/users/42").body(axum::body::Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn records_the_same_attributes_as_the_http_server_layer() -> anyhow::Result<()> {
+    use axum::routing::get;
+    use tower::ServiceExt;
+    use tracing::instrument::WithSubscriber;
+
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    // Each request gets a span from the layer, and a child span from the middleware.
+    let app = axum::Router::new()
+        .route("/users/:id", get(|| async { StatusCode::NO_CONTENT }))
+        .route_layer(axum::middleware::from_fn(
+            |request: axum::http::Request<axum::body::Body>,
+             next: axum::middleware::Next<axum::body::Body>| async move {
+                let request_info = HttpRequestInfo::new(&request);
+                global_tracer()
+                    .in_span_async("response", "response", SpanVisibility::User, || {
+                        Box::pin(async move {
+                            let response = next.run(request).await;
+                            TraceableHttpResponse::for_request(response, request_info)
+                        })
+                    })
+                    .await
+                    .response
+            },
+        ))
+        .layer(ddn_tracing::http_server::layer());
+    let request = axum::http::Request::get("/users/42").body(axum::body::Body::empty())?;
+    let response = app
+        .oneshot(request)
+        .with_subscriber(scoped.subscriber())
+        .await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    // The span ends with the response body.
+    drop(response);
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let spans = collector_state.read_spans();
+    assert_eq!(spans.len(), 2);
+    for span in spans {
+        for (key, expected) in [("method", "GET"), ("uri", "/users/42")] {
+            assert_eq!(
+                attribute(&span.attributes, key),
+                Some(proto::any_value::Value::StringValue(expected.to_owned())),
+                "Unexpected value for {key} in {}.",
+                span.name
+            );
+        }
+    }
+
+    Ok(())
+}
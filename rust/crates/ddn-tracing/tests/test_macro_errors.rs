@@ -0,0 +1,11 @@
+//! Checks that the procedural macros reject invalid input with clear errors.
+
+#[test]
+fn rejects_invalid_traceable_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/traceable_error_*.rs");
+}
+
+#[test]
+fn rejects_invalid_traced_functions() {
+    trybuild::TestCases::new().compile_fail("tests/ui/traced_*.rs");
+}
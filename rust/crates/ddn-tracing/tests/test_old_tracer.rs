@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::{fmt, io};
+
+use ddn_tracing::old::{
+    global_tracer, set_attribute_on_active_span, AttributeVisibility, ErrorVisibility,
+    SpanVisibility, TraceableError, TraceableFutureExt,
+};
+use ddn_tracing::setup::{Builder, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::{attribute, proto, string_attribute, SHUTDOWN_TIMEOUT};
+
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "connection refused")]
+struct ConnectionError;
+
+impl TraceableError for ConnectionError {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+}
+
+#[derive(Debug)]
+struct LoadError(io::Error);
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load metadata")
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl TraceableError for LoadError {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::Internal
+    }
+
+    fn as_error(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn traces_spans_with_the_global_tracer() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let global = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .init()?;
+    let model = "users".to_owned();
+    let result = async {
+        // Span names can be built at runtime.
+        global_tracer().in_span(
+            format!("select {model}"),
+            model.clone(),
+            SpanVisibility::User,
+            || Ok::<_, ConnectionError>(()),
+        )?;
+        async { Err::<(), _>(ConnectionError) }
+            .traced("connect", SpanVisibility::Internal)
+            .await
+    }
+    .traced("request", SpanVisibility::User)
+    .await;
+    assert!(result.is_err());
+    let report = global.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let spans = collector_state.read_spans();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("No span named {name:?}: {spans:?}"))
+    };
+    let request = span("request");
+    let select = span("select users");
+    let connect = span("connect");
+
+    assert_eq!(select.parent_span_id, request.span_id);
+    assert_eq!(
+        string_attribute(&select.attributes, "display.name").as_deref(),
+        Some("users")
+    );
+    assert_eq!(connect.parent_span_id, request.span_id);
+    assert_eq!(
+        string_attribute(&request.attributes, "display.name").as_deref(),
+        Some("request")
+    );
+    assert_eq!(
+        string_attribute(&request.attributes, "internal.visibility").as_deref(),
+        Some("user")
+    );
+    // Internal errors are hidden on user-visible spans.
+    assert_eq!(
+        request
+            .status
+            .as_ref()
+            .map(|status| status.message.as_str()),
+        Some("Internal error")
+    );
+    assert_eq!(
+        connect
+            .status
+            .as_ref()
+            .map(|status| status.message.as_str()),
+        Some("connection refused")
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shares_one_span_tree_with_tracing() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    let result = tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", otel.name = "request").in_scope(|| {
+            global_tracer().in_span("plan", "plan", SpanVisibility::Internal, || {
+                tracing::info_span!("span", otel.name = "fetch").in_scope(|| {
+                    set_attribute_on_active_span(AttributeVisibility::Internal, "rows", 3);
+                });
+                set_attribute_on_active_span(AttributeVisibility::Default, "model", "users");
+                Ok::<_, ConnectionError>(())
+            })
+        })
+    });
+    assert!(result.is_ok());
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let spans = collector_state.read_spans();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("No span named {name:?}: {spans:?}"))
+    };
+    let request = span("request");
+    let plan = span("plan");
+    let fetch = span("fetch");
+
+    assert_eq!(plan.trace_id, request.trace_id);
+    assert_eq!(plan.parent_span_id, request.span_id);
+    assert_eq!(fetch.parent_span_id, plan.span_id);
+    assert_eq!(
+        string_attribute(&plan.attributes, "internal.visibility").as_deref(),
+        Some("internal")
+    );
+    assert_eq!(
+        string_attribute(&plan.attributes, "model").as_deref(),
+        Some("users")
+    );
+    assert!(fetch
+        .attributes
+        .iter()
+        .any(|attribute| attribute.key == "internal.rows"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn records_errors_as_exception_events() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    let source = || io::Error::new(io::ErrorKind::NotFound, "no such file");
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        let tracer = global_tracer();
+        tracer
+            .in_span("load", "load", SpanVisibility::Internal, || {
+                Err::<(), Box<dyn Error + Send + Sync>>(LoadError(source()).into())
+            })
+            .ok();
+        tracer
+            .in_span("request", "request", SpanVisibility::User, || {
+                Err::<(), _>(LoadError(source()))
+            })
+            .ok();
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let spans = collector_state.read_spans();
+    let exception = |name: &str| {
+        let span = spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("No span named {name:?}: {spans:?}"));
+        span.events
+            .iter()
+            .find(|event| event.name == "exception")
+            .unwrap_or_else(|| panic!("No exception event on {name:?}: {span:?}"))
+            .clone()
+    };
+    let stacktrace = |event: &proto::span::Event, key: &str| match attribute(&event.attributes, key)
+    {
+        Some(proto::any_value::Value::ArrayValue(array)) => Some(array.values.len()),
+        _ => None,
+    };
+
+    let load = exception("load");
+    assert_eq!(
+        string_attribute(&load.attributes, "exception.message").as_deref(),
+        Some("failed to load metadata")
+    );
+    assert_eq!(stacktrace(&load, "exception.stacktrace"), Some(1));
+    // The type of a boxed error is not known.
+    assert_eq!(attribute(&load.attributes, "exception.type"), None);
+
+    // Internal errors are hidden on user-visible spans.
+    let request = exception("request");
+    assert_eq!(
+        string_attribute(&request.attributes, "exception.message").as_deref(),
+        Some("Internal error")
+    );
+    assert_eq!(stacktrace(&request, "exception.stacktrace"), None);
+    assert_eq!(
+        stacktrace(&request, "internal.exception.stacktrace"),
+        Some(1)
+    );
+    assert_eq!(attribute(&request.attributes, "exception.type"), None);
+    assert!(attribute(&request.attributes, "internal.exception.type").is_some());
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Configures exporters through the environment, so this is kept apart from
+//! other tests, which would otherwise see the same variables.
+
+use ddn_tracing::setup::{Builder, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::SHUTDOWN_TIMEOUT;
+use tonic::metadata::MetadataMap;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prefers_explicit_settings_to_the_environment() -> anyhow::Result<()> {
+    let first_state = memory_collector::State::new();
+    let first_server = memory_collector::serve_in_background(&first_state).await?;
+    let second_state = memory_collector::State::new();
+    let second_server = memory_collector::serve_in_background(&second_state).await?;
+    let fallback_state = memory_collector::State::new();
+    let fallback_server = memory_collector::serve_in_background(&fallback_state).await?;
+
+    std::env::set_var("OTEL_EXPORTER_OTLP_ENDPOINT", fallback_server.url());
+    std::env::set_var("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=from-env");
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            OtlpExporter::new()
+                .with_endpoint(first_server.url())
+                .with_header("x-api-key", "first"),
+        )
+        .with_exporter(
+            OtlpExporter::new()
+                .with_endpoint(second_server.url())
+                .with_header("x-tenant", "second"),
+        )
+        .with_exporter(OtlpExporter::new())
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span").in_scope(|| {});
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let first = single_request_headers(&first_state);
+    assert_eq!(header_values(&first, "x-api-key"), ["first"]);
+    assert!(header_values(&first, "x-tenant").is_empty());
+
+    let second = single_request_headers(&second_state);
+    assert_eq!(header_values(&second, "x-tenant"), ["second"]);
+    assert!(header_values(&second, "x-api-key").is_empty());
+
+    let fallback = single_request_headers(&fallback_state);
+    assert_eq!(header_values(&fallback, "x-api-key"), ["from-env"]);
+    assert!(header_values(&fallback, "x-tenant").is_empty());
+
+    Ok(())
+}
+
+fn single_request_headers(collector_state: &memory_collector::State) -> MetadataMap {
+    let metadata = collector_state.read_metadata();
+    assert_eq!(metadata.len(), 1, "Unexpected requests: {metadata:?}");
+    metadata.into_iter().next().unwrap()
+}
+
+fn header_values<'a>(metadata: &'a MetadataMap, name: &str) -> Vec<&'a str> {
+    metadata
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect()
+}
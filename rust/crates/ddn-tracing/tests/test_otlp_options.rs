@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use ddn_tracing::setup::{Builder, Compression, OtlpExporter, ScopedTracing, SetupError};
+use ddn_tracing::tracing;
+use memory_collector::certs;
+use memory_collector::tls::{Certificate, Identity, ServerTlsConfig};
+use memory_collector::SHUTDOWN_TIMEOUT;
+use tonic::metadata::MetadataMap;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_headers_and_compresses_requests() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            OtlpExporter::new()
+                .with_endpoint(collector_server.url())
+                .with_header("authorization", "Bearer secret")
+                .with_compression(Compression::Gzip)
+                .with_timeout(Duration::from_secs(1)),
+        )
+        .build()?;
+    emit_span(&scoped, "compressed");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let metadata = collector_state.read_metadata();
+    assert_eq!(metadata.len(), 1);
+    assert_eq!(header(&metadata[0], "authorization"), Some("Bearer secret"));
+    assert_eq!(header(&metadata[0], "grpc-encoding"), Some("gzip"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_over_mutual_tls() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server =
+        memory_collector::serve_in_background_with_tls(&collector_state, mutual_tls_config())
+            .await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            OtlpExporter::new()
+                .with_endpoint(tls_url(&collector_server))
+                .with_ca_certificate(certs::CA_CERTIFICATE)
+                .with_client_identity(certs::CLIENT_CERTIFICATE, certs::CLIENT_KEY),
+        )
+        .build()?;
+    emit_span(&scoped, "secure");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_exported, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_to_export_without_a_client_certificate() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server =
+        memory_collector::serve_in_background_with_tls(&collector_state, mutual_tls_config())
+            .await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            OtlpExporter::new()
+                .with_endpoint(tls_url(&collector_server))
+                .with_ca_certificate(certs::CA_CERTIFICATE),
+        )
+        .build()?;
+    emit_span(&scoped, "rejected");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert_eq!(report.spans_failed, 1, "Unexpected report: {report:?}");
+    assert!(collector_state.read().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn configures_the_exporter_through_the_environment() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server =
+        memory_collector::serve_in_background_with_tls(&collector_state, mutual_tls_config())
+            .await?;
+
+    let echo_server = test_servers::example::start_example(
+        "echo-server",
+        &tls_url(&collector_server),
+        vec![
+            ("OTEL_EXPORTER_OTLP_CERTIFICATE", certs::CA_CERTIFICATE_PATH),
+            (
+                "OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE",
+                certs::CLIENT_CERTIFICATE_PATH,
+            ),
+            ("OTEL_EXPORTER_OTLP_CLIENT_KEY", certs::CLIENT_KEY_PATH),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=secret"),
+            ("OTEL_EXPORTER_OTLP_COMPRESSION", "gzip"),
+            ("OTEL_EXPORTER_OTLP_TIMEOUT", "5000"),
+        ],
+    )
+    .await?;
+
+    reqwest::Client::new()
+        .post(echo_server.url() + "/echo")
+        .body("Hello there!")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    collector_state.wait_for_next_write().await;
+    let metadata = collector_state.read_metadata();
+    assert!(!metadata.is_empty(), "Expected at least one request.");
+    assert_eq!(header(&metadata[0], "x-api-key"), Some("secret"));
+    assert_eq!(header(&metadata[0], "grpc-encoding"), Some("gzip"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_with_an_invalid_header() {
+    let result = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_header("not a header", "value"))
+        .build();
+
+    assert!(
+        matches!(&result, Err(SetupError::InvalidHeader { name, .. }) if name == "not a header"),
+        "Expected an invalid header error."
+    );
+}
+
+/// Requires clients to present a certificate signed by the test CA.
+fn mutual_tls_config() -> ServerTlsConfig {
+    ServerTlsConfig::new()
+        .identity(Identity::from_pem(
+            certs::SERVER_CERTIFICATE,
+            certs::SERVER_KEY,
+        ))
+        .client_ca_root(Certificate::from_pem(certs::CA_CERTIFICATE))
+}
+
+/// The server URL uses plain HTTP.
+fn tls_url(server: &test_servers::server::BackgroundServer) -> String {
+    format!("https://{}", server.local_addr())
+}
+
+fn header<'a>(metadata: &'a MetadataMap, name: &str) -> Option<&'a str> {
+    metadata.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn emit_span(scoped: &ScopedTracing, name: &'static str) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", otel.name = name).in_scope(|| {});
+    });
+}
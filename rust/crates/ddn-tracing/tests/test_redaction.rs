@@ -0,0 +1,106 @@
+use ddn_tracing::export::{Redaction, RedactionMode};
+use ddn_tracing::setup::{Builder, Exporter, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::{string_attribute, SHUTDOWN_TIMEOUT};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn strips_internal_attributes_for_one_exporter_only() -> anyhow::Result<()> {
+    let internal_state = memory_collector::State::new();
+    let internal_server = memory_collector::serve_in_background(&internal_state).await?;
+    let customer_state = memory_collector::State::new();
+    let customer_server = memory_collector::serve_in_background(&customer_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(internal_server.url()))
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(customer_server.url()))
+                .with_redaction(Redaction::new()),
+        )
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!(
+            "span",
+            internal.visibility = "user",
+            internal.error_details = "stack trace",
+            display.name = "query",
+        )
+        .in_scope(|| {});
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let internal_span = internal_state.read_single_span();
+    assert_eq!(
+        string_attribute(&internal_span.attributes, "internal.visibility"),
+        Some("user".to_owned())
+    );
+    assert_eq!(
+        string_attribute(&internal_span.attributes, "internal.error_details"),
+        Some("stack trace".to_owned())
+    );
+
+    let customer_span = customer_state.read_single_span();
+    assert_eq!(
+        string_attribute(&customer_span.attributes, "display.name"),
+        Some("query".to_owned())
+    );
+    assert!(
+        customer_span
+            .attributes
+            .iter()
+            .all(|attribute| !attribute.key.starts_with("internal.")),
+        "Unexpected attributes: {:?}",
+        customer_span.attributes
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hashes_sensitive_attributes_on_spans_and_events() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(collector_server.url()))
+                .with_redaction(
+                    Redaction::new()
+                        .with_mode(RedactionMode::Hash {
+                            salt: b"secret".to_vec(),
+                        })
+                        .with_sensitive_key("user.email"),
+                ),
+        )
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!(
+            "span",
+            user.email = "alice@example.com",
+            user.role = "admin"
+        )
+        .in_scope(|| {
+            tracing::info!(user.email = "alice@example.com", "logged in");
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    let hashed = string_attribute(&span.attributes, "user.email").unwrap();
+    assert!(hashed.starts_with("sha256:"), "Unexpected value: {hashed}");
+    assert!(!hashed.contains("alice"), "Unexpected value: {hashed}");
+    assert_eq!(
+        string_attribute(&span.attributes, "user.role"),
+        Some("admin".to_owned())
+    );
+
+    assert_eq!(span.events.len(), 1);
+    assert_eq!(
+        string_attribute(&span.events[0].attributes, "user.email"),
+        Some(hashed),
+        "Equal values should have equal hashes"
+    );
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use memory_collector::string_attribute;
 use opentelemetry_semantic_conventions as semcov;
 
 #[tokio::test(flavor = "multi_thread")]
@@ -61,3 +62,122 @@ async fn defines_resource_attributes() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn detects_and_merges_resource_attributes() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let echo_server = test_servers::example::start_example(
+        "echo-server",
+        &collector_server.url(),
+        vec![
+            ("K8S_POD_NAME", "echo-server-abc123"),
+            ("K8S_NAMESPACE_NAME", "detected-namespace"),
+            ("K8S_NODE_NAME", "node-1"),
+            ("DEPLOYMENT_ENVIRONMENT", "testing"),
+            (
+                "OTEL_RESOURCE_ATTRIBUTES",
+                "k8s.namespace.name=overridden-namespace,team=observability",
+            ),
+        ],
+    )
+    .await?;
+
+    reqwest::Client::new()
+        .post(echo_server.url() + "/echo")
+        .body("Hello there!")
+        .send()
+        .await?;
+
+    collector_state.wait_for_next_write().await;
+    let spans = collector_state.read();
+    assert!(!spans.is_empty(), "Expected at least one span.");
+
+    for span in spans {
+        let Some(resource) = span.resource else {
+            anyhow::bail!("Found a span without a resource.");
+        };
+        let attributes = resource.attributes;
+
+        for (key, expected) in [
+            (semcov::resource::K8S_POD_NAME, "echo-server-abc123"),
+            (semcov::resource::K8S_NAMESPACE_NAME, "overridden-namespace"),
+            (semcov::resource::K8S_NODE_NAME, "node-1"),
+            (semcov::resource::DEPLOYMENT_ENVIRONMENT, "testing"),
+            (semcov::resource::OS_TYPE, std::env::consts::OS),
+            (semcov::resource::PROCESS_RUNTIME_NAME, "rustc"),
+            ("team", "observability"),
+        ] {
+            assert_eq!(
+                string_attribute(&attributes, key).as_deref(),
+                Some(expected),
+                "Unexpected value for {key}."
+            );
+        }
+
+        for key in [
+            semcov::resource::HOST_NAME,
+            semcov::resource::PROCESS_PID,
+            semcov::resource::PROCESS_RUNTIME_VERSION,
+        ] {
+            assert!(
+                attributes.iter().any(|attribute| attribute.key == key),
+                "Expected {key} to be detected."
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn defines_service_namespace_and_instance_id() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let echo_server = test_servers::example::start_example(
+        "echo-server",
+        &collector_server.url(),
+        vec![("SERVICE_NAMESPACE", "testing-namespace")],
+    )
+    .await?;
+
+    reqwest::Client::new()
+        .post(echo_server.url() + "/echo")
+        .body("Hello there!")
+        .send()
+        .await?;
+
+    collector_state.wait_for_next_write().await;
+    let spans = collector_state.read();
+    assert!(!spans.is_empty(), "Expected at least one span.");
+
+    let mut instance_ids = std::collections::HashSet::new();
+    for span in spans {
+        let Some(resource) = span.resource else {
+            anyhow::bail!("Found a span without a resource.");
+        };
+        let attributes = resource.attributes;
+
+        assert_eq!(
+            string_attribute(&attributes, semcov::resource::SERVICE_NAMESPACE).as_deref(),
+            Some("testing-namespace")
+        );
+
+        let Some(instance_id) =
+            string_attribute(&attributes, semcov::resource::SERVICE_INSTANCE_ID)
+        else {
+            anyhow::bail!("Found a resource without a service instance ID.");
+        };
+        assert_eq!(
+            instance_id.len(),
+            36,
+            "Expected a UUID, got {instance_id:?}."
+        );
+        instance_ids.insert(instance_id);
+    }
+    assert_eq!(instance_ids.len(), 1, "Expected a single instance ID.");
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ddn_tracing::export::{ExportResult, RetryQueue, SpanData, SpanExporter};
+use ddn_tracing::setup::{BatchSettings, Builder, Exporter, OtlpExporter, ScopedTracing};
+use ddn_tracing::tracing;
+use futures_util::future::BoxFuture;
+use memory_collector::SHUTDOWN_TIMEOUT;
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spills_spans_during_an_outage_and_sends_them_afterwards() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+    let directory = tempfile::tempdir()?;
+
+    collector_state.set_unavailable(true);
+    let scoped = build(&collector_server.url(), directory.path())?;
+    emit_span(&scoped, "during outage");
+    flush(&scoped)?;
+
+    assert_eq!(spilled_files(directory.path())?, 1);
+    assert!(collector_state.read().is_empty());
+
+    collector_state.set_unavailable(false);
+    tokio::time::timeout(WAIT_TIMEOUT, collector_state.wait_for_next_write()).await?;
+    wait_until_empty(directory.path()).await?;
+
+    assert_eq!(collector_state.read_span_names(), vec!["during outage"]);
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_spilled, 1);
+    assert_eq!(report.spans_exported, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spills_batches_which_time_out() -> anyhow::Result<()> {
+    let directory = tempfile::tempdir()?;
+    let exporter = HangingExporter::default();
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(exporter.clone()).with_retry_queue(
+            RetryQueue::new(directory.path()).with_initial_backoff(Duration::from_millis(10)),
+        ))
+        .with_batch_settings(
+            BatchSettings::new()
+                .with_scheduled_delay(Duration::from_millis(10))
+                .with_max_export_timeout(Duration::from_millis(100)),
+        )
+        .build()?;
+    emit_span(&scoped, "slow");
+    tokio::time::timeout(WAIT_TIMEOUT, async {
+        while exporter.exports.load(Ordering::Relaxed) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    wait_until_empty(directory.path()).await?;
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_spilled, 1);
+    assert_eq!(report.spans_exported, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_spans_spilled_by_a_previous_process() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+    let directory = tempfile::tempdir()?;
+
+    collector_state.set_unavailable(true);
+    let scoped = build(&collector_server.url(), directory.path())?;
+    emit_span(&scoped, "before restart");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(spilled_files(directory.path())?, 1);
+
+    collector_state.set_unavailable(false);
+    let scoped = build(&collector_server.url(), directory.path())?;
+    emit_span(&scoped, "after restart");
+    flush(&scoped)?;
+    wait_until_empty(directory.path()).await?;
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    let mut names = collector_state.read_span_names();
+    names.sort();
+    assert_eq!(names, vec!["after restart", "before restart"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_to_export_when_the_retry_queue_is_full() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+    let directory = tempfile::tempdir()?;
+
+    collector_state.set_unavailable(true);
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(collector_server.url()))
+                .with_retry_queue(RetryQueue::new(directory.path()).with_max_size(1)),
+        )
+        .build()?;
+    emit_span(&scoped, "too big");
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert_eq!(report.spans_failed, 1, "Unexpected report: {report:?}");
+    assert_eq!(spilled_files(directory.path())?, 0);
+
+    Ok(())
+}
+
+fn build(endpoint: &str, directory: &Path) -> anyhow::Result<ScopedTracing> {
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            Exporter::from(
+                OtlpExporter::new()
+                    .with_endpoint(endpoint)
+                    .with_timeout(Duration::from_secs(1)),
+            )
+            .with_retry_queue(
+                RetryQueue::new(directory)
+                    .with_initial_backoff(Duration::from_millis(100))
+                    .with_max_backoff(Duration::from_millis(200)),
+            ),
+        )
+        .with_batch_settings(BatchSettings::new().with_scheduled_delay(Duration::from_millis(50)))
+        .build()?;
+    Ok(scoped)
+}
+
+fn emit_span(scoped: &ScopedTracing, name: &'static str) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", otel.name = name).in_scope(|| {});
+    });
+}
+
+fn flush(scoped: &ScopedTracing) -> anyhow::Result<()> {
+    for result in scoped.tracer_provider().unwrap().force_flush() {
+        result?;
+    }
+    Ok(())
+}
+
+fn spilled_files(directory: &Path) -> std::io::Result<usize> {
+    match std::fs::read_dir(directory) {
+        Ok(entries) => Ok(entries.count()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(error),
+    }
+}
+
+async fn wait_until_empty(directory: &Path) -> anyhow::Result<()> {
+    tokio::time::timeout(WAIT_TIMEOUT, async {
+        while spilled_files(directory)? > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok::<_, std::io::Error>(())
+    })
+    .await??;
+    Ok(())
+}
+
+/// Hangs on the first export, and succeeds afterwards.
+#[derive(Clone, Debug, Default)]
+struct HangingExporter {
+    exports: Arc<AtomicUsize>,
+}
+
+impl SpanExporter for HangingExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        if self.exports.fetch_add(1, Ordering::Relaxed) == 0 {
+            Box::pin(futures_util::future::pending())
+        } else {
+            Box::pin(async { Ok(()) })
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Sets `RUST_LOG`, so this is kept apart from other tests, which would
+//! otherwise see it.
+
+use ddn_tracing::setup::{Builder, SetupError};
+
+#[tokio::test]
+async fn skips_invalid_directives_unless_strict() -> anyhow::Result<()> {
+    std::env::set_var("RUST_LOG", "warn,my_crate=not_a_level");
+
+    let _scoped = Builder::new("test", "1.0.0").build()?;
+
+    let result = Builder::new("test", "1.0.0")
+        .with_strict_env_filter()
+        .build();
+    let Err(error) = result else {
+        panic!("Expected setup to fail.");
+    };
+    assert!(
+        matches!(&error, SetupError::InvalidEnvVar { name, .. } if *name == "RUST_LOG"),
+        "Expected an invalid RUST_LOG error, got: {error}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::net;
+use std::time::Duration;
+
+use ddn_tracing::export::{ExportResult, SpanData, SpanExporter};
+use ddn_tracing::setup::{Builder, Exporter, OtlpExporter, ScopedTracing};
+use ddn_tracing::tracing;
+use futures_util::future::BoxFuture;
+use memory_collector::SHUTDOWN_TIMEOUT;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_spans_without_installing_globally() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_endpoint(collector_server.url())
+        .build()?;
+    emit_span(&scoped, "scoped");
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_exported, 1);
+    assert_eq!(collector_state.read_span_names(), vec!["scoped"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn supports_multiple_configurations_at_once() -> anyhow::Result<()> {
+    let first_collector_state = memory_collector::State::new();
+    let first_collector_server =
+        memory_collector::serve_in_background(&first_collector_state).await?;
+    let second_collector_state = memory_collector::State::new();
+    let second_collector_server =
+        memory_collector::serve_in_background(&second_collector_state).await?;
+
+    let first = Builder::new("first", "1.0.0")
+        .with_endpoint(first_collector_server.url())
+        .build()?;
+    let second = Builder::new("second", "1.0.0")
+        .with_endpoint(second_collector_server.url())
+        .build()?;
+    emit_span(&first, "one");
+    emit_span(&second, "two");
+
+    let first_report = first.shutdown(SHUTDOWN_TIMEOUT).await;
+    let second_report = second.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(
+        first_report.is_complete(),
+        "Unexpected report: {first_report:?}"
+    );
+    assert!(
+        second_report.is_complete(),
+        "Unexpected report: {second_report:?}"
+    );
+    assert_eq!(first_collector_state.read_span_names(), vec!["one"]);
+    assert_eq!(second_collector_state.read_span_names(), vec!["two"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_spans_that_failed_to_export() -> anyhow::Result<()> {
+    // Find a port with nothing listening on it.
+    let unused_address = net::TcpListener::bind((net::Ipv6Addr::LOCALHOST, 0))?.local_addr()?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_endpoint(format!("http://{unused_address}"))
+        .build()?;
+    emit_span(&scoped, "lost");
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(!report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_exported, 0);
+    assert_eq!(report.spans_failed, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fans_out_to_multiple_exporters() -> anyhow::Result<()> {
+    let first_collector_state = memory_collector::State::new();
+    let first_collector_server =
+        memory_collector::serve_in_background(&first_collector_state).await?;
+    let second_collector_state = memory_collector::State::new();
+    let second_collector_server =
+        memory_collector::serve_in_background(&second_collector_state).await?;
+    let unused_address = net::TcpListener::bind((net::Ipv6Addr::LOCALHOST, 0))?.local_addr()?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(first_collector_server.url()))
+        .with_exporter(OtlpExporter::new().with_endpoint(second_collector_server.url()))
+        .with_exporter(OtlpExporter::new().with_endpoint(format!("http://{unused_address}")))
+        .build()?;
+    emit_span(&scoped, "everywhere");
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert_eq!(report.spans_exported, 2);
+    assert_eq!(report.spans_failed, 1);
+    assert_eq!(first_collector_state.read_span_names(), vec!["everywhere"]);
+    assert_eq!(second_collector_state.read_span_names(), vec!["everywhere"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn does_not_hold_up_exporters_behind_a_slow_one() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(SlowExporter))
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    emit_span(&scoped, "quick");
+    let shutdown = tokio::spawn(scoped.shutdown(SHUTDOWN_TIMEOUT));
+
+    tokio::time::timeout(
+        SlowExporter::DELAY / 2,
+        collector_state.wait_for_next_write(),
+    )
+    .await?;
+    assert_eq!(collector_state.read_span_names(), vec!["quick"]);
+
+    let report = shutdown.await?;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    Ok(())
+}
+
+/// An exporter which takes a long time to export anything.
+#[derive(Debug)]
+struct SlowExporter;
+
+impl SlowExporter {
+    const DELAY: Duration = Duration::from_secs(2);
+}
+
+impl SpanExporter for SlowExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        Box::pin(async {
+            tokio::time::sleep(Self::DELAY).await;
+            Ok(())
+        })
+    }
+}
+
+fn emit_span(scoped: &ScopedTracing, name: &'static str) {
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", otel.name = name).in_scope(|| {
+            tracing::info!("inside a span");
+        });
+    });
+}
@@ -0,0 +1,130 @@
+use ddn_tracing::setup::{Builder, OtlpExporter, Scrubber};
+use ddn_tracing::tracing;
+use memory_collector::{string_attribute, SHUTDOWN_TIMEOUT};
+use opentelemetry::trace::{
+    Link, Span as _, SpanContext, SpanId, TraceFlags, TraceId, TraceState, Tracer as _,
+    TracerProvider as _,
+};
+use opentelemetry::KeyValue;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrubs_span_and_event_attributes_for_every_exporter() -> anyhow::Result<()> {
+    let first_state = memory_collector::State::new();
+    let first_server = memory_collector::serve_in_background(&first_state).await?;
+    let second_state = memory_collector::State::new();
+    let second_server = memory_collector::serve_in_background(&second_state).await?;
+
+    let scrubber = Scrubber::new()
+        .with_common_patterns()
+        .with_sensitive_key("graphql.variables");
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(first_server.url()))
+        .with_exporter(OtlpExporter::new().with_endpoint(second_server.url()))
+        .with_scrubber(scrubber.clone())
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!(
+            "span",
+            db.statement = "SELECT * FROM users WHERE email = 'alice@example.com' OR email = 'bob@example.com'",
+            graphql.variables = r#"{"id": 1}"#,
+            graphql.operation = "GetUser",
+        )
+        .in_scope(|| {
+            tracing::info!(
+                http.authorization = "Bearer abc.def-123",
+                card = "4111 1111 1111 1111",
+                "request received"
+            );
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    for state in [&first_state, &second_state] {
+        let span = state.read_single_span();
+        assert_eq!(
+            string_attribute(&span.attributes, "db.statement").as_deref(),
+            Some("SELECT * FROM users WHERE email = '[REDACTED]' OR email = '[REDACTED]'")
+        );
+        assert_eq!(
+            string_attribute(&span.attributes, "graphql.variables").as_deref(),
+            Some("[REDACTED]")
+        );
+        assert_eq!(
+            string_attribute(&span.attributes, "graphql.operation").as_deref(),
+            Some("GetUser")
+        );
+
+        assert_eq!(span.events.len(), 1);
+        let event_attributes = &span.events[0].attributes;
+        assert_eq!(
+            string_attribute(event_attributes, "http.authorization").as_deref(),
+            Some("[REDACTED]")
+        );
+        assert_eq!(
+            string_attribute(event_attributes, "card").as_deref(),
+            Some("[REDACTED]")
+        );
+    }
+
+    // Each span is scrubbed once, however many exporters there are.
+    assert_eq!(scrubber.redactions(), 5);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrubs_link_attributes_and_only_valid_card_numbers() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scrubber = Scrubber::new().with_common_patterns();
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .with_scrubber(scrubber.clone())
+        .build()?;
+    let tracer = scoped
+        .tracer_provider()
+        .expect("tracer provider")
+        .tracer("test");
+    let linked = SpanContext::new(
+        TraceId::from_bytes([1; 16]),
+        SpanId::from_bytes([1; 8]),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    tracer
+        .span_builder("linked")
+        .with_attributes(vec![
+            // This fails the Luhn check, so it is not a card number.
+            KeyValue::new("request.id", "1234 5678 9012 3456"),
+            KeyValue::new("card", "4111-1111-1111-1111"),
+        ])
+        .with_links(vec![Link::new(
+            linked,
+            vec![KeyValue::new("user", "alice@example.com")],
+        )])
+        .start(&tracer)
+        .end();
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    assert_eq!(
+        string_attribute(&span.attributes, "request.id").as_deref(),
+        Some("1234 5678 9012 3456")
+    );
+    assert_eq!(
+        string_attribute(&span.attributes, "card").as_deref(),
+        Some("[REDACTED]")
+    );
+    assert_eq!(span.links.len(), 1);
+    assert_eq!(
+        string_attribute(&span.links[0].attributes, "user").as_deref(),
+        Some("[REDACTED]")
+    );
+    assert_eq!(scrubber.redactions(), 2);
+
+    Ok(())
+}
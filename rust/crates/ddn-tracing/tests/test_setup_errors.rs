@@ -0,0 +1,63 @@
+use std::error::Error;
+
+use ddn_tracing::setup::{Builder, Scrubber, SetupError};
+use ddn_tracing::tracing;
+
+#[test]
+fn fails_without_a_tokio_runtime() {
+    let result = Builder::new("test", "1.0.0").init();
+
+    assert!(
+        matches!(result, Err(SetupError::NoTokioRuntime { .. })),
+        "Expected a missing runtime error."
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_with_an_invalid_endpoint() {
+    let result = Builder::new("test", "1.0.0")
+        .with_endpoint("http://not a valid host")
+        .init();
+
+    let Err(error) = result else {
+        panic!("Expected setup to fail.");
+    };
+    assert!(
+        matches!(&error, SetupError::InvalidEndpoint { endpoint, .. } if endpoint == "http://not a valid host"),
+        "Expected an invalid endpoint error, got: {error}"
+    );
+    assert!(error.source().is_some(), "Expected a source error.");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_with_an_invalid_scrubbing_pattern() {
+    let result = Builder::new("test", "1.0.0")
+        .with_scrubber(Scrubber::new().with_pattern("(unclosed"))
+        .build();
+
+    let Err(error) = result else {
+        panic!("Expected setup to fail.");
+    };
+    assert!(
+        matches!(&error, SetupError::InvalidScrubPattern { pattern, .. } if pattern == "(unclosed"),
+        "Expected an invalid pattern error, got: {error}"
+    );
+    assert!(error.source().is_some(), "Expected a source error.");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_if_a_global_subscriber_is_already_set() {
+    tracing::subscriber::set_global_default(tracing::subscriber::NoSubscriber::default())
+        .expect("Could not set the global subscriber.");
+
+    let result = Builder::new("test", "1.0.0").init();
+
+    let Err(error) = result else {
+        panic!("Expected setup to fail.");
+    };
+    assert!(
+        matches!(error, SetupError::SubscriberAlreadySet { .. }),
+        "Expected a subscriber error, got: {error}"
+    );
+    assert!(error.source().is_some(), "Expected a source error.");
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use ddn_tracing::export::{ExportResult, SpanData, SpanExporter};
+use ddn_tracing::setup::{Builder, Exporter};
+use ddn_tracing::tracing;
+use futures_util::future::BoxFuture;
+use memory_collector::SHUTDOWN_TIMEOUT;
+use opentelemetry_sdk::metrics::data::{ResourceMetrics, Temporality};
+use opentelemetry_sdk::metrics::reader::{AggregationSelector, MetricReader, TemporalitySelector};
+use opentelemetry_sdk::metrics::{Aggregation, InstrumentKind, ManualReader, Pipeline};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flushes_pending_spans_on_shutdown() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let global_tracing = ddn_tracing::setup::Builder::new("test", "1.0.0")
+        .with_endpoint(collector_server.url())
+        .init()?;
+
+    tracing::info_span!("pending").in_scope(|| {
+        tracing::info!("inside a span");
+    });
+
+    let report = global_tracing.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(report.spans_exported, 1);
+    assert_eq!(report.spans_failed, 0);
+
+    assert_eq!(collector_state.read_span_names(), vec!["pending"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flushes_metrics_and_logs_on_shutdown() -> anyhow::Result<()> {
+    let metric_flushes = Arc::new(AtomicUsize::new(0));
+    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+        .with_reader(FlushCountingReader {
+            inner: ManualReader::builder().build(),
+            flushes: metric_flushes.clone(),
+        })
+        .build();
+    let log_flushes = Arc::new(AtomicUsize::new(0));
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(NoopExporter))
+        .with_meter_provider(meter_provider)
+        .with_log_writer({
+            let log_flushes = log_flushes.clone();
+            move || FlushCountingWriter(log_flushes.clone())
+        })
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info!("logged");
+    });
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+    assert_eq!(metric_flushes.load(Ordering::SeqCst), 1);
+    assert_eq!(log_flushes.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn does_not_block_when_dropped_in_a_current_thread_runtime() -> anyhow::Result<()> {
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(Exporter::custom(NoopExporter))
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("pending").in_scope(|| {});
+    });
+
+    // The export task cannot run while dropping blocks this thread, so
+    // waiting for it would always time out.
+    let start = Instant::now();
+    drop(scoped);
+
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "Dropping took {:?}",
+        start.elapsed()
+    );
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct NoopExporter;
+
+impl SpanExporter for NoopExporter {
+    fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Counts how often it is flushed, and discards everything written to it.
+struct FlushCountingWriter(Arc<AtomicUsize>);
+
+impl io::Write for FlushCountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// A metric reader which counts how often it is flushed.
+#[derive(Debug)]
+struct FlushCountingReader {
+    inner: ManualReader,
+    flushes: Arc<AtomicUsize>,
+}
+
+impl TemporalitySelector for FlushCountingReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.inner.temporality(kind)
+    }
+}
+
+impl AggregationSelector for FlushCountingReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.inner.aggregation(kind)
+    }
+}
+
+impl MetricReader for FlushCountingReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.inner.register_pipeline(pipeline);
+    }
+
+    fn collect(&self, metrics: &mut ResourceMetrics) -> opentelemetry::metrics::Result<()> {
+        self.inner.collect(metrics)
+    }
+
+    fn force_flush(&self) -> opentelemetry::metrics::Result<()> {
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> opentelemetry::metrics::Result<()> {
+        self.inner.shutdown()
+    }
+}
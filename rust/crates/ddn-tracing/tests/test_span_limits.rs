@@ -0,0 +1,102 @@
+use ddn_tracing::setup::{Builder, OtlpExporter, SpanLimits, TRUNCATION_MARKER};
+use ddn_tracing::tracing;
+use memory_collector::{string_attribute, SHUTDOWN_TIMEOUT};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn truncates_long_attribute_values_with_a_marker() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .with_span_limits(SpanLimits::new().with_max_attribute_value_length(20))
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!(
+            "span",
+            details = "a very long debug representation",
+            short = "ok"
+        )
+        .in_scope(|| {
+            // The marker counts towards the limit, leaving 6 bytes of the
+            // value, and "é" is two bytes, so this is cut before it.
+            tracing::info!(query = "abcdeé and a lot more", "event");
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    assert_eq!(
+        string_attribute(&span.attributes, "details"),
+        Some(format!("a very{TRUNCATION_MARKER}"))
+    );
+    assert_eq!(
+        string_attribute(&span.attributes, "short"),
+        Some("ok".to_owned())
+    );
+    assert_eq!(
+        string_attribute(&span.events[0].attributes, "query"),
+        Some(format!("abcde{TRUNCATION_MARKER}"))
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drops_attributes_and_events_beyond_the_limits() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .with_span_limits(
+            SpanLimits::new()
+                .with_max_attributes_per_span(2)
+                .with_max_events_per_span(1),
+        )
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", first = 1, second = 2, third = 3, fourth = 4).in_scope(|| {
+            tracing::info!("first event");
+            tracing::info!("second event");
+            tracing::info!("third event");
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    assert_eq!(span.attributes.len(), 2);
+    assert!(
+        span.dropped_attributes_count > 0,
+        "Expected dropped attributes to be counted: {span:?}"
+    );
+    assert_eq!(span.events.len(), 1);
+    assert_eq!(span.dropped_events_count, 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn truncates_very_long_values_by_default() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    let backtrace = "frame\n".repeat(10_000);
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        tracing::info_span!("span", backtrace = backtrace.as_str()).in_scope(|| {});
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let span = collector_state.read_single_span();
+    let truncated = string_attribute(&span.attributes, "backtrace").expect("no backtrace");
+    assert_eq!(truncated.len(), 16 * 1024);
+    assert!(truncated.ends_with(TRUNCATION_MARKER));
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Configures span limits through the environment, so this is kept apart
+//! from other tests, which would otherwise see the same variables.
+
+use ddn_tracing::setup::{Builder, OtlpExporter, SpanLimits};
+use ddn_tracing::tracing;
+use memory_collector::SHUTDOWN_TIMEOUT;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prefers_explicit_limits_to_the_environment() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    std::env::set_var("OTEL_SPAN_EVENT_COUNT_LIMIT", "1");
+    for (name, span_limits) in [
+        ("explicit", SpanLimits::new().with_max_events_per_span(3)),
+        ("fallback", SpanLimits::new()),
+    ] {
+        let scoped = Builder::new("test", "1.0.0")
+            .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+            .with_span_limits(span_limits)
+            .build()?;
+        tracing::subscriber::with_default(scoped.subscriber(), || {
+            tracing::info_span!("span", otel.name = name).in_scope(|| {
+                for _ in 0..3 {
+                    tracing::info!("event");
+                }
+            });
+        });
+        let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+        assert!(report.is_complete(), "Unexpected report: {report:?}");
+    }
+
+    let spans = collector_state.read_spans();
+    let events = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .map(|span| span.events.len())
+    };
+    assert_eq!(events("explicit"), Some(3));
+    assert_eq!(events("fallback"), Some(1));
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+
+use ddn_tracing::old::{global_tracer, ErrorVisibility, SpanVisibility, Traceable, TraceableError};
+
+fn error_of<R: Traceable>(result: &R) -> Option<(bool, String, String)> {
+    result.get_error().map(|error| {
+        (
+            matches!(error.visibility(), ErrorVisibility::User),
+            error.description(),
+            error.details(),
+        )
+    })
+}
+
+#[test]
+fn values_without_errors_are_traceable() {
+    assert!(error_of(&()).is_none());
+    assert!(error_of(&Some(1)).is_none());
+    assert!(error_of(&None::<u8>).is_none());
+    assert!(error_of(&vec!["a", "b"]).is_none());
+
+    // Ordinary code can be run in a span.
+    let tracer = global_tracer();
+    tracer.in_span("unit", "unit", SpanVisibility::Internal, || {});
+    let found = tracer.in_span("option", "option", SpanVisibility::Internal, || {
+        ["a", "b"].into_iter().find(|value| *value == "b")
+    });
+    assert_eq!(found, Some("b"));
+}
+
+#[test]
+fn common_errors_are_internal() {
+    let boxed: Result<(), Box<dyn Error + Send + Sync>> = Err("invalid configuration".into());
+    assert_eq!(
+        error_of(&boxed),
+        Some((
+            false,
+            "invalid configuration".to_owned(),
+            r#""invalid configuration""#.to_owned()
+        ))
+    );
+}
+
+#[cfg(feature = "io")]
+#[test]
+fn io_errors_are_internal() {
+    use std::io;
+
+    let io: io::Result<()> = Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+    let (is_user, description, _) = error_of(&io).expect("expected an error");
+    assert!(!is_user);
+    assert_eq!(description, "no such file");
+}
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn anyhow_errors_are_internal() {
+    use std::io;
+
+    use anyhow::Context;
+
+    let result: anyhow::Result<()> =
+        Err(io::Error::new(io::ErrorKind::NotFound, "no such file")).context("loading metadata");
+    let (is_user, description, details) = error_of(&result).expect("expected an error");
+    assert!(!is_user);
+    assert_eq!(description, "loading metadata");
+    assert!(
+        details.contains("no such file"),
+        "Unexpected details: {details}"
+    );
+}
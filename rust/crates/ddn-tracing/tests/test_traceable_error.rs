@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use ddn_tracing::old::{ErrorVisibility, TraceableError};
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "connection refused by {host}")]
+#[traceable(visibility = "internal", details = "host: {host}, port: {port}")]
+struct ConnectorError {
+    host: String,
+    port: u16,
+}
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "{_0}")]
+#[traceable(visibility = "user")]
+struct WrappedError(#[traceable(delegate)] ConnectorError);
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+enum FetchError {
+    #[display(fmt = "model {_0} not found")]
+    #[traceable(visibility = "user")]
+    ModelNotFound(String),
+    #[display(fmt = "{_0}")]
+    Connector(#[traceable(delegate)] ConnectorError),
+    #[display(fmt = "timed out")]
+    #[traceable(visibility = "internal", description = "timed out after {0:?}")]
+    Timeout(Duration),
+    #[display(fmt = "{source}")]
+    #[traceable(visibility = "user", details = "while fetching {model}")]
+    Wrapped {
+        model: String,
+        #[traceable(delegate)]
+        source: ConnectorError,
+    },
+}
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[traceable(visibility = "user")]
+enum RequestError {
+    #[display(fmt = "{_0}")]
+    Connector(#[traceable(delegate)] ConnectorError),
+    #[display(fmt = "{_0}")]
+    #[traceable(visibility = "internal")]
+    Fetch(#[traceable(delegate)] FetchError),
+}
+
+fn connector_error() -> ConnectorError {
+    ConnectorError {
+        host: "db".to_owned(),
+        port: 5432,
+    }
+}
+
+fn is_user(error: &impl TraceableError) -> bool {
+    matches!(error.visibility(), ErrorVisibility::User)
+}
+
+#[test]
+fn derives_for_structs() {
+    let error = connector_error();
+    assert!(!is_user(&error));
+    assert_eq!(error.description(), "connection refused by db");
+    assert_eq!(error.details(), "host: db, port: 5432");
+
+    // An explicit visibility takes precedence over the delegated one.
+    let error = WrappedError(connector_error());
+    assert!(is_user(&error));
+    assert_eq!(error.description(), "connection refused by db");
+    assert_eq!(error.details(), "host: db, port: 5432");
+}
+
+#[test]
+fn derives_for_enums() {
+    let error = FetchError::ModelNotFound("users".to_owned());
+    assert!(is_user(&error));
+    assert_eq!(error.description(), "model users not found");
+    assert_eq!(error.details(), r#"ModelNotFound("users")"#);
+
+    let error = FetchError::Connector(connector_error());
+    assert!(!is_user(&error));
+    assert_eq!(error.description(), "connection refused by db");
+    assert_eq!(error.details(), "host: db, port: 5432");
+
+    let error = FetchError::Timeout(Duration::from_secs(3));
+    assert!(!is_user(&error));
+    assert_eq!(error.description(), "timed out after 3s");
+
+    let error = FetchError::Wrapped {
+        model: "users".to_owned(),
+        source: connector_error(),
+    };
+    assert!(is_user(&error));
+    assert_eq!(error.description(), "connection refused by db");
+    assert_eq!(error.details(), "while fetching users");
+}
+
+#[test]
+fn takes_type_names_from_delegates() {
+    let connector_type = std::any::type_name::<ConnectorError>();
+    assert_eq!(
+        WrappedError(connector_error()).type_name(),
+        Some(connector_type)
+    );
+    assert_eq!(
+        FetchError::Connector(connector_error()).type_name(),
+        Some(connector_type)
+    );
+    assert_eq!(
+        FetchError::Timeout(Duration::from_secs(3)).type_name(),
+        Some(std::any::type_name::<FetchError>())
+    );
+}
+
+#[test]
+fn prefers_explicit_visibilities_to_delegates() {
+    // A visibility on an enum takes precedence over the delegate's.
+    let error = RequestError::Connector(connector_error());
+    assert!(is_user(&error));
+    assert_eq!(error.details(), "host: db, port: 5432");
+
+    // A visibility on a variant takes precedence over the enum's.
+    let error = RequestError::Fetch(FetchError::ModelNotFound("users".to_owned()));
+    assert!(!is_user(&error));
+    assert_eq!(error.description(), "model users not found");
+}
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "failed to load {path}")]
+#[traceable(error)]
+struct LoadError {
+    path: String,
+    source: std::io::Error,
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[test]
+fn records_the_source_chain_of_errors() {
+    let error = LoadError {
+        path: "metadata.json".to_owned(),
+        source: std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"),
+    };
+    let source = error
+        .as_error()
+        .and_then(std::error::Error::source)
+        .map(ToString::to_string);
+    assert_eq!(source.as_deref(), Some("no such file"));
+
+    // Without it, only a delegate's chain is known.
+    assert!(connector_error().as_error().is_none());
+}
@@ -0,0 +1,102 @@
+use ddn_tracing::old::{ErrorVisibility, TraceableError};
+use ddn_tracing::setup::{Builder, OtlpExporter};
+use ddn_tracing::{traced, tracing};
+use memory_collector::{string_attribute, SHUTDOWN_TIMEOUT};
+
+#[derive(Debug, derive_more::Display)]
+enum QueryError {
+    #[display(fmt = "limit too large")]
+    LimitTooLarge,
+}
+
+impl TraceableError for QueryError {
+    fn visibility(&self) -> ErrorVisibility {
+        ErrorVisibility::User
+    }
+}
+
+struct Connector {
+    max_limit: usize,
+}
+
+impl Connector {
+    #[traced(attributes(model, limit))]
+    fn select(&self, model: &str, limit: usize) -> Result<usize, QueryError> {
+        if limit > self.max_limit {
+            return Err(QueryError::LimitTooLarge);
+        }
+        Ok(model.len() + limit)
+    }
+
+    #[traced(name = "fetch rows", display_name = "Fetch", visibility = "user")]
+    async fn fetch(&self, model: String, limit: usize) -> Result<usize, QueryError> {
+        tokio::task::yield_now().await;
+        if limit == 0 {
+            return Ok(0);
+        }
+        let rows = self.select(&model, limit)?;
+        Ok(rows)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn traces_annotated_functions() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(collector_server.url()))
+        .build()?;
+    let connector = Connector { max_limit: 100 };
+    {
+        let _default = tracing::subscriber::set_default(scoped.subscriber());
+        assert_eq!(connector.fetch("users".to_owned(), 10).await.ok(), Some(15));
+        assert!(connector.fetch("users".to_owned(), 1000).await.is_err());
+        // An early return from an async function still ends its span.
+        assert_eq!(connector.fetch("users".to_owned(), 0).await.ok(), Some(0));
+    }
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let spans = collector_state.read_spans();
+    assert_eq!(spans.len(), 5, "Unexpected spans: {spans:?}");
+    let (fetches, selects): (Vec<_>, Vec<_>) =
+        spans.iter().partition(|span| span.name == "fetch rows");
+    assert!(selects.iter().all(|span| span.name == "select"));
+
+    for fetch in &fetches {
+        assert_eq!(
+            string_attribute(&fetch.attributes, "display.name").as_deref(),
+            Some("Fetch")
+        );
+        assert_eq!(
+            string_attribute(&fetch.attributes, "internal.visibility").as_deref(),
+            Some("user")
+        );
+    }
+    for select in &selects {
+        assert!(fetches
+            .iter()
+            .any(|fetch| fetch.span_id == select.parent_span_id));
+        assert_eq!(
+            string_attribute(&select.attributes, "internal.visibility").as_deref(),
+            Some("internal")
+        );
+        assert_eq!(
+            string_attribute(&select.attributes, "model").as_deref(),
+            Some("users")
+        );
+    }
+
+    // The early return from the sync function is recorded as an error.
+    let failed = selects
+        .iter()
+        .find(|span| string_attribute(&span.attributes, "limit").as_deref() == Some("1000"))
+        .expect("no select span with a limit of 1000");
+    assert_eq!(
+        failed.status.as_ref().map(|status| status.message.as_str()),
+        Some("limit too large")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ddn_tracing::setup::{BatchSettings, Builder, Exporter, OtlpExporter};
+use ddn_tracing::tracing;
+use memory_collector::SHUTDOWN_TIMEOUT;
+use opentelemetry::trace::{Span as _, TraceContextExt, Tracer as _, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_only_user_spans_re_parented_onto_user_ancestors() -> anyhow::Result<()> {
+    let internal_state = memory_collector::State::new();
+    let internal_server = memory_collector::serve_in_background(&internal_state).await?;
+    let customer_state = memory_collector::State::new();
+    let customer_server = memory_collector::serve_in_background(&customer_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(OtlpExporter::new().with_endpoint(internal_server.url()))
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(customer_server.url()))
+                .with_user_visible_spans_only(),
+        )
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        span("request", "user").in_scope(|| {
+            span("plan", "internal").in_scope(|| {
+                span("fetch", "user").in_scope(|| {
+                    span("sql", "internal").in_scope(|| {
+                        span("connector", "user").in_scope(|| {});
+                    });
+                });
+            });
+            tracing::info_span!("unlabelled").in_scope(|| {});
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    let internal_parents = parents_by_name(&internal_state);
+    assert_eq!(internal_parents.len(), 6);
+    assert_eq!(internal_parents["fetch"].as_deref(), Some("plan"));
+
+    let customer_parents = parents_by_name(&customer_state);
+    assert_eq!(
+        customer_parents,
+        HashMap::from([
+            ("request".to_owned(), None),
+            ("fetch".to_owned(), Some("request".to_owned())),
+            ("connector".to_owned(), Some("fetch".to_owned())),
+        ])
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn makes_children_of_an_internal_root_into_roots() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(collector_server.url()))
+                .with_user_visible_spans_only(),
+        )
+        .build()?;
+    tracing::subscriber::with_default(scoped.subscriber(), || {
+        span("startup", "internal").in_scope(|| {
+            span("load metadata", "user").in_scope(|| {});
+        });
+    });
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    assert_eq!(
+        parents_by_name(&collector_state),
+        HashMap::from([("load metadata".to_owned(), None)])
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwards_spans_which_end_after_their_local_root() -> anyhow::Result<()> {
+    let collector_state = memory_collector::State::new();
+    let collector_server = memory_collector::serve_in_background(&collector_state).await?;
+
+    let scoped = Builder::new("test", "1.0.0")
+        .with_exporter(
+            Exporter::from(OtlpExporter::new().with_endpoint(collector_server.url()))
+                .with_user_visible_spans_only(),
+        )
+        .with_batch_settings(BatchSettings::new().with_scheduled_delay(Duration::from_millis(10)))
+        .build()?;
+    let tracer = scoped
+        .tracer_provider()
+        .expect("tracer provider")
+        .tracer("test");
+    let user_span = |name: &'static str, cx: &Context| {
+        tracer
+            .span_builder(name)
+            .with_attributes(vec![KeyValue::new("internal.visibility", "user")])
+            .start_with_context(&tracer, cx)
+    };
+
+    let request = Context::new().with_span(user_span("request", &Context::new()));
+    let mut background = user_span("background", &request);
+    request.span().end();
+    wait_for_spans(&collector_state, &["request"]).await;
+    // This is not held back until shutdown, as the trace's root has ended.
+    background.end();
+    wait_for_spans(&collector_state, &["request", "background"]).await;
+
+    let report = scoped.shutdown(SHUTDOWN_TIMEOUT).await;
+    assert!(report.is_complete(), "Unexpected report: {report:?}");
+
+    Ok(())
+}
+
+fn span(name: &'static str, visibility: &'static str) -> tracing::Span {
+    tracing::info_span!("span", otel.name = name, internal.visibility = visibility)
+}
+
+/// Maps the name of each span to the name of its parent, if its parent was
+/// exported.
+fn parents_by_name(collector_state: &memory_collector::State) -> HashMap<String, Option<String>> {
+    let spans = collector_state.read_spans();
+    let names = spans
+        .iter()
+        .map(|span| (span.span_id.clone(), span.name.clone()))
+        .collect::<HashMap<_, _>>();
+    spans
+        .iter()
+        .map(|span| (span.name.clone(), names.get(&span.parent_span_id).cloned()))
+        .collect()
+}
+
+/// Waits for exactly the given spans to be exported, in any order.
+async fn wait_for_spans(collector_state: &memory_collector::State, expected: &[&str]) {
+    let mut expected = expected.to_vec();
+    expected.sort_unstable();
+    let mut names = Vec::new();
+    for _ in 0..100 {
+        names = collector_state.read_span_names();
+        names.sort();
+        if names == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Expected spans {expected:?}, but got {names:?}.");
+}
@@ -0,0 +1,8 @@
+use ddn_tracing::old::TraceableError;
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "connection refused")]
+#[traceable(visibility = "public")]
+struct ConnectionError;
+
+fn main() {}
@@ -0,0 +1,14 @@
+use ddn_tracing::old::TraceableError;
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "connection refused")]
+struct ConnectionError;
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+#[display(fmt = "{_0}")]
+struct WrappedError(
+    #[traceable(delegate)] ConnectionError,
+    #[traceable(delegate)] ConnectionError,
+);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use ddn_tracing::old::TraceableError;
+
+#[derive(TraceableError)]
+union Error {
+    code: u32,
+    signal: i32,
+}
+
+fn main() {}
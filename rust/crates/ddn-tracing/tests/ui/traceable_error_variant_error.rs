@@ -0,0 +1,10 @@
+use ddn_tracing::old::TraceableError;
+
+#[derive(Debug, derive_more::Display, TraceableError)]
+enum FetchError {
+    #[display(fmt = "timed out")]
+    #[traceable(error)]
+    Timeout,
+}
+
+fn main() {}
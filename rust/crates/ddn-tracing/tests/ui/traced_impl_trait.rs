@@ -0,0 +1,8 @@
+use ddn_tracing::traced;
+
+#[traced]
+fn numbers() -> Option<impl Iterator<Item = u32>> {
+    Some(0..3)
+}
+
+fn main() {}
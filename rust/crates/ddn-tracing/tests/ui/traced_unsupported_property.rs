@@ -0,0 +1,8 @@
+use ddn_tracing::traced;
+
+#[traced(level = "info")]
+fn select(model: &str) -> Option<usize> {
+    Some(model.len())
+}
+
+fn main() {}
@@ -2,17 +2,31 @@
 //!
 //! This will gather any spans sent via gRPC and store the deserialized Protocol
 //! Buffers structures. They can later be read.
+//!
+//! Spans can also be loaded from files in the OTLP JSON encoding, such as
+//! those written by `ddn_tracing::export::FileExporter`.
+//!
+//! The server accepts gzip-compressed requests, and can be served over TLS
+//! using the test certificates in [`certs`].
+//!
+//! A collector outage can be simulated with [`State::set_unavailable`].
 
+use std::io::{self, BufRead, Write};
 use std::net;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 
 use opentelemetry_proto::tonic::collector::trace::v1::*;
 use tokio::net::TcpListener;
 use tokio::sync::Notify;
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::MetadataMap;
 use tonic::transport::server::{Router, TcpIncoming};
-use tonic::transport::Server;
+use tonic::transport::{Server, ServerTlsConfig};
 
 use test_servers::server::{BackgroundServer, BackgroundServerBuilder};
 
@@ -22,13 +36,77 @@ pub mod proto {
     pub use opentelemetry_proto::tonic::trace::v1::*;
 }
 
+pub mod tls {
+    pub use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+}
+
+/// Certificates for testing TLS, valid for 100 years.
+///
+/// The server certificate is valid for `localhost`, `127.0.0.1` and `::1`.
+/// The server and client certificates are both signed by the CA certificate.
+pub mod certs {
+    pub const CA_CERTIFICATE: &[u8] = include_bytes!("../certs/ca.pem");
+    pub const CA_CERTIFICATE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/ca.pem");
+    pub const SERVER_CERTIFICATE: &[u8] = include_bytes!("../certs/server.pem");
+    pub const SERVER_KEY: &[u8] = include_bytes!("../certs/server.key");
+    pub const CLIENT_CERTIFICATE: &[u8] = include_bytes!("../certs/client.pem");
+    pub const CLIENT_CERTIFICATE_PATH: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/certs/client.pem");
+    pub const CLIENT_KEY: &[u8] = include_bytes!("../certs/client.key");
+    pub const CLIENT_KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/client.key");
+}
+
+/// How long tests should wait for tracing to shut down and flush its spans.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Finds the value of the attribute with the given key.
+pub fn attribute(attributes: &[proto::KeyValue], key: &str) -> Option<proto::any_value::Value> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.key == key)
+        .and_then(|attribute| attribute.value.clone()?.value)
+}
+
+/// Finds the value of the attribute with the given key, if it is a string.
+pub fn string_attribute(attributes: &[proto::KeyValue], key: &str) -> Option<String> {
+    match attribute(attributes, key)? {
+        proto::any_value::Value::StringValue(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// A buffer which output, such as logs, can be written to and then read back.
+///
+/// A clone of this will share the underlying buffer.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Gets everything written up until now.
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// The collector state. Create a new one to use it.
 ///
 /// A clone of this will share the underlying state.
 #[derive(Clone)]
 pub struct State {
     spans: Arc<RwLock<Vec<proto::ResourceSpans>>>,
+    metadata: Arc<RwLock<Vec<MetadataMap>>>,
     notify: Arc<Notify>,
+    unavailable: Arc<AtomicBool>,
 }
 
 impl State {
@@ -36,10 +114,18 @@ impl State {
     pub fn new() -> Self {
         Self {
             spans: Arc::new(RwLock::new(Vec::new())),
+            metadata: Arc::new(RwLock::new(Vec::new())),
             notify: Arc::new(Notify::new()),
+            unavailable: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Simulates a collector outage. While unavailable, every export is
+    /// rejected with `UNAVAILABLE`, and its spans are not recorded.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
     /// Appends a new set of spans to the list.
     fn append(&self, mut resource_spans: Vec<proto::ResourceSpans>) {
         let mut spans = self.spans.write().unwrap();
@@ -47,12 +133,71 @@ impl State {
         self.notify.notify_one();
     }
 
+    /// Loads spans from a file in the OTLP JSON encoding, which contains one
+    /// `TracesData` message per line.
+    pub fn load_otlp_json_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::open(path)?;
+        self.load_otlp_json(io::BufReader::new(file))
+    }
+
+    /// Loads spans in the OTLP JSON encoding, which contains one `TracesData`
+    /// message per line.
+    pub fn load_otlp_json(&self, reader: impl BufRead) -> anyhow::Result<()> {
+        let mut resource_spans = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let traces_data: proto::TracesData = serde_json::from_str(&line)?;
+            resource_spans.extend(traces_data.resource_spans);
+        }
+        self.append(resource_spans);
+        Ok(())
+    }
+
     /// Gets all the spans recorded up until now.
     pub fn read(&self) -> Vec<proto::ResourceSpans> {
         let spans = self.spans.read().unwrap();
         spans.clone()
     }
 
+    /// Gets all the spans recorded up until now, regardless of their resource
+    /// or instrumentation scope.
+    pub fn read_spans(&self) -> Vec<proto::Span> {
+        self.read()
+            .into_iter()
+            .flat_map(|resource_spans| resource_spans.scope_spans)
+            .flat_map(|scope_spans| scope_spans.spans)
+            .collect()
+    }
+
+    /// Gets the only span recorded up until now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one span.
+    pub fn read_single_span(&self) -> proto::Span {
+        let mut spans = self.read_spans();
+        assert_eq!(spans.len(), 1, "Unexpected spans: {spans:?}");
+        spans.remove(0)
+    }
+
+    /// Gets the names of all the spans recorded up until now.
+    pub fn read_span_names(&self) -> Vec<String> {
+        self.read_spans()
+            .into_iter()
+            .map(|span| span.name)
+            .collect()
+    }
+
+    /// Gets the metadata (i.e. the headers) of every request received up
+    /// until now.
+    pub fn read_metadata(&self) -> Vec<MetadataMap> {
+        let metadata = self.metadata.read().unwrap();
+        metadata.clone()
+    }
+
     /// Wait for the next write.
     pub async fn wait_for_next_write(&self) {
         self.notify.notified().await;
@@ -76,6 +221,14 @@ impl trace_service_server::TraceService for TraceServiceHandler {
         &self,
         request: tonic::Request<ExportTraceServiceRequest>,
     ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        if self.state.unavailable.load(Ordering::SeqCst) {
+            return Err(tonic::Status::unavailable("the collector is unavailable"));
+        }
+        self.state
+            .metadata
+            .write()
+            .unwrap()
+            .push(request.metadata().clone());
         let resource_spans = request.into_inner().resource_spans;
         self.state.append(resource_spans);
         Ok(tonic::Response::new(ExportTraceServiceResponse {
@@ -91,21 +244,30 @@ impl trace_service_server::TraceService for TraceServiceHandler {
 ///
 /// Runs in the foreground.
 pub async fn serve(state: &State, address: impl Into<net::SocketAddr>) -> anyhow::Result<()> {
-    let router = create_router(state);
+    let router = create_router(state, None)?;
     router.serve(address.into()).await?;
     Ok(())
 }
 
 pub struct BackgroundTracingServer {
     state: State,
+    tls: Option<ServerTlsConfig>,
 }
 
 impl BackgroundTracingServer {
     pub fn new(state: &State) -> Self {
         Self {
             state: state.clone(),
+            tls: None,
         }
     }
+
+    /// Serves over TLS instead of plain text.
+    #[must_use]
+    pub fn with_tls(mut self, tls: ServerTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
 }
 
 #[test_servers::async_trait]
@@ -113,9 +275,16 @@ impl BackgroundServerBuilder for BackgroundTracingServer {
     type Server = (Router, TcpIncoming);
 
     async fn create_server(&self) -> anyhow::Result<(Self::Server, net::SocketAddr)> {
-        let router = create_router(&self.state);
+        let router = create_router(&self.state, self.tls.clone())?;
 
-        let listener = TcpListener::bind((net::IpAddr::V6(net::Ipv6Addr::LOCALHOST), 0)).await?;
+        // TLS clients cannot verify an IPv6 address as the server name, as it
+        // is written in brackets in the URL, so we use IPv4 instead.
+        let host = if self.tls.is_some() {
+            net::IpAddr::V4(net::Ipv4Addr::LOCALHOST)
+        } else {
+            net::IpAddr::V6(net::Ipv6Addr::LOCALHOST)
+        };
+        let listener = TcpListener::bind((host, 0)).await?;
         let address = listener.local_addr()?;
         let incoming = TcpIncoming::from_listener(listener, false, None)
             .map_err(|error| anyhow::anyhow!(error))?;
@@ -142,11 +311,31 @@ pub async fn serve_in_background(state: &State) -> anyhow::Result<BackgroundServ
     test_servers::server::serve_in_background(BackgroundTracingServer::new(state)).await
 }
 
-fn create_router(state: &State) -> Router {
+/// Creates a new in-memory collection server on the specified TCP listener,
+/// served over TLS.
+///
+/// The server listens on `127.0.0.1`. The URL reported by the server uses
+/// plain HTTP, so clients should connect to `https://<address>` instead.
+///
+/// Runs in the background.
+pub async fn serve_in_background_with_tls(
+    state: &State,
+    tls: ServerTlsConfig,
+) -> anyhow::Result<BackgroundServer> {
+    test_servers::server::serve_in_background(BackgroundTracingServer::new(state).with_tls(tls))
+        .await
+}
+
+fn create_router(state: &State, tls: Option<ServerTlsConfig>) -> anyhow::Result<Router> {
     let trace_service_handler = TraceServiceHandler {
         state: state.clone(),
     };
-    Server::builder().add_service(trace_service_server::TraceServiceServer::new(
-        trace_service_handler,
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        server = server.tls_config(tls)?;
+    }
+    Ok(server.add_service(
+        trace_service_server::TraceServiceServer::new(trace_service_handler)
+            .accept_compressed(CompressionEncoding::Gzip),
     ))
 }
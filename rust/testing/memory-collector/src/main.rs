@@ -1,12 +1,20 @@
+use std::env;
 use std::net;
 
 use memory_collector::*;
 
 const PORT: u16 = 50051;
 
+/// Serves the collector. Any arguments are paths to OTLP JSON files, which are
+/// loaded first.
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let state = State::new();
+    for path in env::args_os().skip(1) {
+        state.load_otlp_json_file(path)?;
+    }
+
     let host = net::IpAddr::V6(net::Ipv6Addr::LOCALHOST);
     let address = net::SocketAddr::new(host, PORT);
-    serve(&State::new(), address).await
+    serve(&state, address).await
 }
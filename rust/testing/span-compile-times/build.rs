@@ -0,0 +1,93 @@
+//! Generates the call sites compiled by this crate. See `src/lib.rs`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_CALL_SITES: usize = 400;
+const DEFAULT_NESTING: usize = 8;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=SPAN_CALL_SITES");
+    println!("cargo:rerun-if-env-changed=SPAN_NESTING");
+    let call_sites = env_var("SPAN_CALL_SITES", DEFAULT_CALL_SITES);
+    let nesting = env_var("SPAN_NESTING", DEFAULT_NESTING).max(1);
+
+    let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is not set");
+    for (feature, style, file_name) in [
+        ("CARGO_FEATURE_BOXED", Style::Boxed, "boxed.rs"),
+        ("CARGO_FEATURE_GENERIC", Style::Generic, "generic.rs"),
+    ] {
+        if env::var_os(feature).is_some() {
+            fs::write(
+                Path::new(&out_dir).join(file_name),
+                generate(call_sites, nesting, style),
+            )
+            .expect("failed to write the call sites");
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Style {
+    Boxed,
+    Generic,
+}
+
+/// Generates chains of `nesting` call sites, each of which awaits the
+/// previous one inside its span, and a function which boxes the head of each
+/// chain, so that every call site is compiled.
+fn generate(call_sites: usize, nesting: usize, style: Style) -> String {
+    let mut source = String::new();
+    let mut heads = Vec::new();
+    for index in 0..call_sites {
+        let body = if index % nesting == 0 {
+            "input".to_owned()
+        } else {
+            format!("call_site_{}(tracer, input).await?", index - 1)
+        };
+        let future = format!(
+            "async move {{ let value = {body}; Ok(value + {}) }}",
+            index + 1
+        );
+        let traced = match style {
+            Style::Boxed => format!(
+                "tracer.in_span_async(\"call_site_{index}\", \"Call site {index}\", \
+                 SpanVisibility::Internal, || Box::pin({future})).await"
+            ),
+            Style::Generic => format!(
+                "tracer.in_span_future(\"call_site_{index}\", \"Call site {index}\", \
+                 SpanVisibility::Internal, {future}).await"
+            ),
+        };
+        writeln!(
+            source,
+            "async fn call_site_{index}(tracer: &Tracer, input: u64) -> Result<u64, Infallible> \
+             {{ {traced} }}"
+        )
+        .unwrap();
+        if index % nesting == nesting - 1 || index == call_sites - 1 {
+            heads.push(index);
+        }
+    }
+
+    source.push_str(
+        "/// Boxes the head of each chain of call sites.\n\
+         pub fn call_sites(tracer: &Tracer, input: u64) -> Vec<CallSite<'_>> {\n    vec![\n",
+    );
+    for head in heads {
+        writeln!(source, "        Box::pin(call_site_{head}(tracer, input)),").unwrap();
+    }
+    source.push_str("    ]\n}\n");
+    source
+}
+
+fn env_var(name: &str, default: usize) -> usize {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} must be a number, not {value:?}")),
+        Err(_) => default,
+    }
+}
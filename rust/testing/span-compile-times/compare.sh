@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+#
+# Builds the fixture with boxed call sites, then with generic ones, and prints
+# how long each build took. Pass `--release` to compare release builds.
+#
+# Set SPAN_CALL_SITES and SPAN_NESTING to change the shape of the fixture.
+
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+# Build the dependencies first, so that only the fixture itself is timed.
+cargo build --quiet "$@" --package span-compile-times
+
+for style in boxed generic; do
+  cargo clean --quiet "$@" --package span-compile-times
+  start="$(date +%s.%N)"
+  cargo build --quiet "$@" --package span-compile-times --features "$style"
+  end="$(date +%s.%N)"
+  awk -v style="$style" -v start="$start" -v end="$end" 'BEGIN { printf "%-8s %6.1fs\n", style, end - start }'
+done
@@ -0,0 +1,47 @@
+//! A fixture for comparing the compile times of `Tracer::in_span_async`,
+//! which boxes futures, with `Tracer::in_span_future`, which is generic over
+//! them, as each generic call site is monomorphized separately.
+//!
+//! With the `boxed` or `generic` feature, this generates a module of chains
+//! of call sites in that style, each awaiting the previous one inside its
+//! span, as nested handlers do in the engine. `SPAN_CALL_SITES` sets the
+//! number of call sites (400 by default), and `SPAN_NESTING` the length of
+//! each chain (8 by default).
+//!
+//! Run `compare.sh` in this directory to build both styles and print how
+//! long each took, as the timings depend on the machine and toolchain.
+//!
+//! Long chains of generic call sites can fail to compile, as checking that
+//! the nested futures are `Send` overflows the default recursion limit, so
+//! deeply nested ones need boxing somewhere, or a higher `recursion_limit`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// The future returned by the head of a chain of call sites.
+pub type CallSite<'a> =
+    Pin<Box<dyn Future<Output = Result<u64, std::convert::Infallible>> + Send + 'a>>;
+
+/// Call sites using `Tracer::in_span_async`.
+#[cfg(feature = "boxed")]
+pub mod boxed {
+    use std::convert::Infallible;
+
+    use ddn_tracing::old::{SpanVisibility, Tracer};
+
+    use super::CallSite;
+
+    include!(concat!(env!("OUT_DIR"), "/boxed.rs"));
+}
+
+/// Call sites using `Tracer::in_span_future`.
+#[cfg(feature = "generic")]
+pub mod generic {
+    use std::convert::Infallible;
+
+    use ddn_tracing::old::{SpanVisibility, Tracer};
+
+    use super::CallSite;
+
+    include!(concat!(env!("OUT_DIR"), "/generic.rs"));
+}
@@ -20,8 +20,11 @@ async fn main() -> anyhow::Result<()> {
 
     let service_name = env!("CARGO_BIN_NAME");
     let service_version = env!("CARGO_PKG_VERSION");
-    let _global_tracing = ddn_tracing::setup::init_tracing(None, service_name, service_version)
-        .map_err(|e| anyhow::anyhow!(e))?;
+    let mut tracing_builder = ddn_tracing::setup::Builder::new(service_name, service_version);
+    if let Ok(namespace) = env::var("SERVICE_NAMESPACE") {
+        tracing_builder = tracing_builder.with_service_namespace(namespace);
+    }
+    let _global_tracing = tracing_builder.init()?;
 
     let app = axum::Router::new()
         .route(